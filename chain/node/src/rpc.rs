@@ -32,9 +32,13 @@ where
     C: Send + Sync + 'static,
     C::Api: substrate_frame_rpc_system::AccountNonceApi<Block, AccountId, Index>,
     C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance>,
+    C::Api: pallet_transaction_payment_pow_rpc::TransactionPaymentPowRuntimeApi<Block, AccountId>,
+    C::Api: pallet_game_rpc::GameRuntimeApi<Block, AccountId>,
     C::Api: BlockBuilder<Block>,
     P: TransactionPool + 'static,
 {
+    use pallet_game_rpc::{Game, GameApi};
+    use pallet_transaction_payment_pow_rpc::{TransactionPaymentPow, TransactionPaymentPowApi};
     use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApi};
     use substrate_frame_rpc_system::{FullSystem, SystemApi};
 
@@ -55,10 +59,11 @@ where
         client.clone(),
     )));
 
-    // Extend this RPC with a custom API by using the following syntax.
-    // `YourRpcStruct` should have a reference to a client, which is needed
-    // to call into the runtime.
-    // `io.extend_with(YourRpcTrait::to_delegate(YourRpcStruct::new(ReferenceToClient, ...)));`
+    io.extend_with(TransactionPaymentPowApi::to_delegate(
+        TransactionPaymentPow::new(client.clone()),
+    ));
+
+    io.extend_with(GameApi::to_delegate(Game::new(client.clone())));
 
     io
 }
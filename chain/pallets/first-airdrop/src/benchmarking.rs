@@ -0,0 +1,17 @@
+//! Benchmarking setup for pallet_first_airdrop
+
+use super::*;
+use frame_benchmarking::{account, benchmarks, whitelisted_caller};
+use frame_system::RawOrigin;
+
+benchmarks! {
+    claim {
+        let caller: T::AccountId = whitelisted_caller();
+        let kusama_account_id: T::AccountId = account("kusama", 0, 0);
+        pallet_game::PlayerFirstAirdropEligible::<T>::insert(&caller, true);
+        PlayerAirdropDestinationKusamaAccountIdCount::<T>::put(499);
+    }: _(RawOrigin::Signed(caller.clone()), kusama_account_id)
+    verify {
+        assert!(PlayerAirdropDestinationKusamaAccountId::<T>::contains_key(&caller));
+    }
+}
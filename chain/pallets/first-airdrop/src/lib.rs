@@ -3,6 +3,11 @@
 use frame_support::{dispatch::DispatchResultWithPostInfo, ensure};
 
 pub use pallet::*;
+pub use weights::WeightInfo;
+
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+pub mod weights;
 
 #[frame_support::pallet]
 pub mod pallet {
@@ -11,7 +16,9 @@ pub mod pallet {
     use frame_system::pallet_prelude::*;
 
     #[pallet::config]
-    pub trait Config: frame_system::Config + pallet_game::Config {}
+    pub trait Config: frame_system::Config + pallet_game::Config {
+        type WeightInfo: WeightInfo;
+    }
 
     #[pallet::pallet]
     #[pallet::generate_store(pub(super) trait Store)]
@@ -35,7 +42,7 @@ pub mod pallet {
 
     #[pallet::call]
     impl<T: Config> Pallet<T> {
-        #[pallet::weight(1)]
+        #[pallet::weight(<T as Config>::WeightInfo::claim())]
         pub fn claim(
             origin: OriginFor<T>,
             kusama_account_id: T::AccountId,
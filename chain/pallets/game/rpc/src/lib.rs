@@ -0,0 +1,162 @@
+//! RPC interface for the player views and the game simulations.
+//! Codec types are passed and returned as SCALE encoded bytes.
+
+use common::codec_types::*;
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use parity_scale_codec::{Codec, Decode, Encode};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::Bytes;
+use sp_rpc::number::NumberOrHex;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+use std::{convert::TryInto, marker::PhantomData, sync::Arc};
+
+pub use pallet_game_runtime_api::GameApi as GameRuntimeApi;
+
+const RUNTIME_ERROR: i64 = 1;
+const SIMULATION_FAILED: i64 = 2;
+
+#[rpc]
+pub trait GameApi<BlockHash, AccountId> {
+    // returns the encoded PlayerView, none if the player is not in a mtc
+    #[rpc(name = "game_playerView")]
+    fn player_view(&self, account: AccountId, at: Option<BlockHash>) -> Result<Option<Bytes>>;
+
+    // returns the encoded (player board grade, ghost board grade)
+    #[rpc(name = "game_simulateBattle")]
+    fn simulate_battle(
+        &self,
+        board: Bytes,
+        ghost_board: Bytes,
+        seed: NumberOrHex,
+        at: Option<BlockHash>,
+    ) -> Result<Bytes>;
+
+    // returns the encoded (board, grade, upgrade coin)
+    #[rpc(name = "game_simulateShop")]
+    fn simulate_shop(
+        &self,
+        board: Bytes,
+        grade: u8,
+        upgrade_coin: Option<u8>,
+        player_operations: Bytes,
+        pool: Bytes,
+        seed: NumberOrHex,
+        turn: u8,
+        at: Option<BlockHash>,
+    ) -> Result<Bytes>;
+}
+
+pub struct Game<C, B> {
+    client: Arc<C>,
+    _marker: PhantomData<B>,
+}
+
+impl<C, B> Game<C, B> {
+    pub fn new(client: Arc<C>) -> Self {
+        Self {
+            client,
+            _marker: Default::default(),
+        }
+    }
+}
+
+impl<C, Block, AccountId> GameApi<<Block as BlockT>::Hash, AccountId> for Game<C, Block>
+where
+    Block: BlockT,
+    C: 'static + ProvideRuntimeApi<Block> + HeaderBackend<Block>,
+    C::Api: GameRuntimeApi<Block, AccountId>,
+    AccountId: Codec,
+{
+    fn player_view(
+        &self,
+        account: AccountId,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Option<Bytes>> {
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+
+        self.client
+            .runtime_api()
+            .player_view(&at, account)
+            .map(|v| v.map(|v| v.encode().into()))
+            .map_err(runtime_error)
+    }
+
+    fn simulate_battle(
+        &self,
+        board: Bytes,
+        ghost_board: Bytes,
+        seed: NumberOrHex,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Bytes> {
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+
+        self.client
+            .runtime_api()
+            .simulate_battle(
+                &at,
+                decode::<mtc::Board>(&board)?,
+                decode::<mtc::GhostBoard>(&ghost_board)?,
+                decode_seed(seed)?,
+            )
+            .map_err(runtime_error)?
+            .map(|r| r.encode().into())
+            .ok_or_else(|| simulation_failed("battle"))
+    }
+
+    fn simulate_shop(
+        &self,
+        board: Bytes,
+        grade: u8,
+        upgrade_coin: Option<u8>,
+        player_operations: Bytes,
+        pool: Bytes,
+        seed: NumberOrHex,
+        turn: u8,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Bytes> {
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+
+        self.client
+            .runtime_api()
+            .simulate_shop(
+                &at,
+                decode::<mtc::Board>(&board)?,
+                grade,
+                upgrade_coin,
+                decode::<Vec<mtc::shop::PlayerOperation>>(&player_operations)?,
+                decode::<Vec<mtc::Emo>>(&pool)?,
+                decode_seed(seed)?,
+                turn,
+            )
+            .map_err(runtime_error)?
+            .map(|r| r.encode().into())
+            .ok_or_else(|| simulation_failed("shop"))
+    }
+}
+
+fn decode<T: Decode>(bytes: &Bytes) -> Result<T> {
+    T::decode(&mut &bytes[..]).map_err(|e| RpcError::invalid_params(e.to_string()))
+}
+
+fn decode_seed(seed: NumberOrHex) -> Result<u64> {
+    seed.try_into()
+        .map_err(|_| RpcError::invalid_params("seed: not u64"))
+}
+
+fn runtime_error<E: std::fmt::Debug>(e: E) -> RpcError {
+    RpcError {
+        code: ErrorCode::ServerError(RUNTIME_ERROR),
+        message: "Unable to call the runtime api.".into(),
+        data: Some(format!("{:?}", e).into()),
+    }
+}
+
+fn simulation_failed(subject: &str) -> RpcError {
+    RpcError {
+        code: ErrorCode::ServerError(SIMULATION_FAILED),
+        message: format!("The {} simulation failed.", subject),
+        data: None,
+    }
+}
@@ -0,0 +1,35 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use common::codec_types::*;
+use parity_scale_codec::Codec;
+use sp_std::prelude::*;
+
+pub use common::codec_types;
+pub use pallet_game::PlayerView;
+
+sp_api::decl_runtime_apis! {
+    pub trait GameApi<AccountId> where AccountId: Codec {
+        // none if the player is not in a mtc
+        fn player_view(account: AccountId) -> Option<PlayerView<AccountId>>;
+
+        // simulates against the emo bases of the block, none if it fails.
+        // returns (player board grade, ghost board grade)
+        fn simulate_battle(
+            board: mtc::Board,
+            ghost_board: mtc::GhostBoard,
+            seed: u64,
+        ) -> Option<(u8, u8)>;
+
+        // simulates against the emo bases of the block, none if it fails.
+        // returns (board, grade, upgrade coin) after the operations
+        fn simulate_shop(
+            board: mtc::Board,
+            grade: u8,
+            upgrade_coin: Option<u8>,
+            player_operations: Vec<mtc::shop::PlayerOperation>,
+            pool: Vec<mtc::Emo>,
+            seed: u64,
+            turn: u8,
+        ) -> Option<(mtc::Board, u8, Option<u8>)>;
+    }
+}
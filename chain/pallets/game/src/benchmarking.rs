@@ -0,0 +1,118 @@
+//! Benchmarking setup for pallet_game with the worst case inputs of common::mtc::worst_case
+
+use super::*;
+use common::mtc::{ep::get_ep_band, utils::BOARD_EMO_MAX_COUNT, worst_case};
+use frame_benchmarking::{account, benchmarks, whitelisted_caller};
+use frame_system::RawOrigin;
+
+const MATCHMAKING_GHOSTS_LEN: u32 = 20;
+
+fn setup_emo_bases<T: Config>() -> (emo::Bases, [u16; 6]) {
+    let emo_bases = worst_case::build_emo_bases();
+    let (built, fixed) = worst_case::build_deck_base_ids();
+
+    <EmoBases<T>>::put(emo_bases.clone());
+    <DeckFixedEmoBaseIds<T>>::put(fixed);
+    <DeckBuiltEmoBaseIds<T>>::put(built.clone());
+
+    let mut deck_emo_base_ids = [0u16; 6];
+    deck_emo_base_ids.copy_from_slice(&built);
+
+    (emo_bases, deck_emo_base_ids)
+}
+
+fn build_ghosts<T: Config>(
+    emo_bases: &emo::Bases,
+    len: u32,
+) -> Vec<(Option<T::AccountId>, u16, mtc_Ghost)> {
+    let ghost = worst_case::build_ghost(emo_bases);
+    (0..len)
+        .map(|i| (Some(account("ghost", i, 0)), INITIAL_EP, ghost.clone()))
+        .collect()
+}
+
+fn setup_matchmaking_ghosts<T: Config>(emo_bases: &emo::Bases, ep_band: u16) {
+    let ghost = worst_case::build_ghost(emo_bases);
+    let mut info = Vec::new();
+
+    for i in 0..MATCHMAKING_GHOSTS_LEN {
+        info.push((0, account("ghost", i, 0), INITIAL_EP));
+        MatchmakingGhostByIndex::<T>::insert((ep_band, i as u8), ghost.clone());
+    }
+
+    MatchmakingGhostsInfo::<T>::insert(ep_band, info);
+}
+
+// the player is in the middle of an mtc, so start_mtc cleans it up
+fn setup_player<T: Config>(player: &T::AccountId, emo_bases: &emo::Bases, deck: &[u16]) {
+    let (built, fixed) = worst_case::build_deck_base_ids();
+
+    PlayerEp::<T>::insert(player, INITIAL_EP);
+    PlayerMainToSession::<T>::insert(player, player);
+    PlayerSessionToMain::<T>::insert(player, player);
+    PlayerSeed::<T>::insert(player, 1);
+    PlayerPool::<T>::insert(player, build_pool(deck, emo_bases, &fixed, &built).unwrap());
+    PlayerHealth::<T>::insert(player, PLAYER_INITIAL_HEALTH);
+    PlayerGradeAndBoardHistory::<T>::insert(player, {
+        let mut history = worst_case::build_grade_and_board_history(emo_bases);
+        // the next turn is the last one allowed
+        history.pop();
+        history
+    });
+    PlayerGhosts::<T>::insert(player, build_ghosts::<T>(emo_bases, 3));
+    PlayerGhostStates::<T>::insert(player, build_initial_ghost_states());
+    PlayerBattleGhostIndex::<T>::insert(player, 0);
+}
+
+benchmarks! {
+    update_emo_bases {
+        let b in 1 .. 200;
+
+        let (mut emo_bases, _) = setup_emo_bases::<T>();
+        let (built, fixed) = worst_case::build_deck_base_ids();
+        let base = emo_bases.0.values().last().unwrap().clone();
+        for i in 0..b {
+            let id = 10_000 + i as u16;
+            emo_bases.0.insert(id, emo::Base { id, ..base.clone() });
+        }
+    }: _(RawOrigin::Root, emo_bases, fixed, built, true)
+
+    start_mtc {
+        let caller: T::AccountId = whitelisted_caller();
+        let (emo_bases, deck) = setup_emo_bases::<T>();
+        setup_player::<T>(&caller, &emo_bases, &deck);
+        setup_matchmaking_ghosts::<T>(&emo_bases, get_ep_band(INITIAL_EP - EP_UNFINISH_PENALTY));
+    }: _(RawOrigin::Signed(caller.clone()), caller.clone(), deck)
+    verify {
+        assert!(PlayerPool::<T>::contains_key(&caller));
+    }
+
+    start_mtc_by_session {
+        let caller: T::AccountId = whitelisted_caller();
+        let (emo_bases, deck) = setup_emo_bases::<T>();
+        setup_player::<T>(&caller, &emo_bases, &deck);
+        setup_matchmaking_ghosts::<T>(&emo_bases, get_ep_band(INITIAL_EP - EP_UNFINISH_PENALTY));
+    }: _(RawOrigin::Signed(caller.clone()), deck)
+    verify {
+        assert!(PlayerPool::<T>::contains_key(&caller));
+    }
+
+    finish_mtc_shop {
+        let o in 1 .. worst_case::PLAYER_OPERATIONS_LEN as u32;
+
+        let caller: T::AccountId = whitelisted_caller();
+        let (emo_bases, deck) = setup_emo_bases::<T>();
+        setup_player::<T>(&caller, &emo_bases, &deck);
+        setup_matchmaking_ghosts::<T>(&emo_bases, get_ep_band(INITIAL_EP));
+
+        let mut ops = worst_case::build_player_operations();
+        ops.truncate(o as usize);
+    }: _(RawOrigin::Signed(caller.clone()), ops)
+    verify {
+        assert_eq!(
+            PlayerGradeAndBoardHistory::<T>::get(&caller)
+                .map(|h| h.last().map(|g| g.board.0.len())),
+            Some(Some(BOARD_EMO_MAX_COUNT as usize))
+        );
+    }
+}
@@ -3,13 +3,20 @@
 use common::{
     codec_types::*,
     mtc::{
-        battle::organizer::{battle_all, select_battle_ghost_index},
+        battle::organizer::{battle_all, march_pvg, select_battle_ghost_index},
         emo_bases::check_and_build_emo_bases,
-        ep::{calculate_new_ep, EP_UNFINISH_PENALTY, INITIAL_EP},
+        ep::{
+            calculate_new_rating, increase_rd, sort_ghost_ratings, Rating, EP_UNFINISH_PENALTY,
+            INITIAL_EP, MAX_RD, RATING_PERIOD_BLOCKS,
+        },
         finish::{exceeds_grade_and_board_history_limit, get_turn_and_previous_grade_and_board},
-        ghost::{build_matchmaking_ghosts, choose_ghosts, separate_player_ghosts},
+        ghost::{
+            get_ghost_ratings, matchmake, register_finished_mtc_ghost, update_recent_opponents,
+            ChooseGhostsOptions, MatchmakingStorage,
+        },
         setup::{build_initial_ghost_states, build_pool, PLAYER_INITIAL_HEALTH},
         shop::{
+            catalog::get_catalog,
             coin::{decrease_upgrade_coin, get_upgrade_coin},
             player_operation::verify_player_operations_and_update,
         },
@@ -17,12 +24,19 @@ use common::{
     utils::partial_bytes_to_u64,
 };
 use frame_support::{
-    debug::native::debug, dispatch::DispatchResultWithPostInfo, traits::Randomness,
+    debug::native::debug, dispatch::DispatchResultWithPostInfo, sp_runtime::SaturatedConversion,
+    traits::Randomness, RuntimeDebug,
 };
-use parity_scale_codec::Encode;
-use sp_std::prelude::*;
+use parity_scale_codec::{Decode, Encode};
+use sp_std::{marker::PhantomData, prelude::*};
 
 pub use pallet::*;
+pub use weights::WeightInfo;
+
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+mod migrations;
+pub mod weights;
 
 mod metadata_names {
     #![allow(non_camel_case_types)]
@@ -39,6 +53,38 @@ mod metadata_names {
 }
 use metadata_names::*;
 
+// the storage layout of the pallet
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum Releases {
+    V0,
+    // the matchmaking ghosts are split into the info and the ghosts by index,
+    // and the players have the rating deviation
+    V1,
+}
+
+impl Default for Releases {
+    fn default() -> Self {
+        Releases::V0
+    }
+}
+
+// the state of a player in a mtc, for the runtime api
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct PlayerView<AccountId> {
+    pub ep: u16,
+    pub turn: u8,
+    pub health: u8,
+    pub grade_and_board_history: Vec<mtc::GradeAndBoard>,
+    pub upgrade_coin: Option<u8>,
+    // bot ghosts have no account
+    pub ghosts: Vec<(Option<AccountId>, u16, mtc::Ghost)>,
+    pub ghost_states: Vec<mtc::GhostState>,
+    pub battle_ghost_index: u8,
+    pub pool: Vec<mtc::Emo>,
+    // the catalog of the next shop
+    pub catalog: mtc::shop::Catalog,
+}
+
 #[frame_support::pallet]
 pub mod pallet {
     use super::*;
@@ -46,12 +92,26 @@ pub mod pallet {
     use frame_system::pallet_prelude::*;
 
     #[pallet::config]
-    pub trait Config: frame_system::Config {}
+    pub trait Config: frame_system::Config {
+        // the opponents of the last n mtcs are excluded from the matchmaking
+        #[pallet::constant]
+        type RecentOpponentsMtcLen: Get<u8>;
+        #[pallet::constant]
+        type UpwardBandSearchLen: Get<u16>;
+        // 0 disables the recency weighting
+        #[pallet::constant]
+        type RecencyHalfLife: Get<u32>;
+
+        type WeightInfo: WeightInfo;
+    }
 
     #[pallet::pallet]
     #[pallet::generate_store(pub(super) trait Store)]
     pub struct Pallet<T>(_);
 
+    #[pallet::storage]
+    pub(super) type StorageVersion<T: Config> = StorageValue<_, Releases, ValueQuery>;
+
     #[pallet::storage]
     pub type EmoBases<T: Config> = StorageValue<_, emo_Bases>;
     #[pallet::storage]
@@ -59,12 +119,23 @@ pub mod pallet {
     #[pallet::storage]
     pub type DeckBuiltEmoBaseIds<T: Config> = StorageValue<_, Vec<u16>>;
 
+    // (registered block number, account, registered ep) by ep band
+    #[pallet::storage]
+    pub type MatchmakingGhostsInfo<T: Config> =
+        StorageMap<_, Blake2_128Concat, u16, Vec<(u32, T::AccountId, u16)>>;
+    #[pallet::storage]
+    pub type MatchmakingGhostByIndex<T: Config> =
+        StorageMap<_, Blake2_128Concat, (u16, u8), mtc_Ghost>;
+    // before Releases::V1, drained by the migration
     #[pallet::storage]
-    pub type MatchmakingGhosts<T: Config> =
+    pub(super) type MatchmakingGhosts<T: Config> =
         StorageMap<_, Blake2_128Concat, u16, Vec<(T::AccountId, u16, mtc_Ghost)>>;
 
     #[pallet::storage]
     pub type PlayerEp<T: Config> = StorageMap<_, Blake2_128Concat, T::AccountId, u16>;
+    // (rating deviation, updated block number)
+    #[pallet::storage]
+    pub type PlayerRd<T: Config> = StorageMap<_, Blake2_128Concat, T::AccountId, (u16, u32)>;
     #[pallet::storage]
     pub type PlayerMainToSession<T: Config> =
         StorageMap<_, Blake2_128Concat, T::AccountId, T::AccountId>;
@@ -72,6 +143,11 @@ pub mod pallet {
     pub type PlayerSessionToMain<T: Config> =
         StorageMap<_, Blake2_128Concat, T::AccountId, T::AccountId>;
 
+    // the opponents of the last mtcs, oldest first
+    #[pallet::storage]
+    pub type PlayerRecentOpponents<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, Vec<T::AccountId>>;
+
     #[pallet::storage]
     pub type PlayerSeed<T: Config> = StorageMap<_, Blake2_128Concat, T::AccountId, u64>;
 
@@ -87,7 +163,7 @@ pub mod pallet {
     pub type PlayerUpgradeCoin<T: Config> = StorageMap<_, Blake2_128Concat, T::AccountId, u8>;
     #[pallet::storage]
     pub type PlayerGhosts<T: Config> =
-        StorageMap<_, Blake2_128Concat, T::AccountId, Vec<(T::AccountId, u16, mtc_Ghost)>>;
+        StorageMap<_, Blake2_128Concat, T::AccountId, Vec<(Option<T::AccountId>, u16, mtc_Ghost)>>;
     #[pallet::storage]
     pub type PlayerGhostStates<T: Config> =
         StorageMap<_, Blake2_128Concat, T::AccountId, Vec<mtc_GhostState>>;
@@ -125,11 +201,21 @@ pub mod pallet {
     }
 
     #[pallet::hooks]
-    impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {}
+    impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+        fn on_runtime_upgrade() -> Weight {
+            if StorageVersion::<T>::get() == Releases::V0 {
+                let weight = migrations::migrate_to_v1::<T>();
+                StorageVersion::<T>::put(Releases::V1);
+                weight.saturating_add(T::DbWeight::get().writes(1))
+            } else {
+                T::DbWeight::get().reads(1)
+            }
+        }
+    }
 
     #[pallet::call]
     impl<T: Config> Pallet<T> {
-        #[pallet::weight(1)]
+        #[pallet::weight(T::WeightInfo::update_emo_bases(new_bases.0.len() as u32))]
         pub fn update_emo_bases(
             origin: OriginFor<T>,
             new_bases: emo_Bases,
@@ -155,7 +241,7 @@ pub mod pallet {
             Ok(().into())
         }
 
-        #[pallet::weight(1)]
+        #[pallet::weight(T::WeightInfo::start_mtc())]
         #[transactional]
         pub fn start_mtc(
             origin: OriginFor<T>,
@@ -167,7 +253,7 @@ pub mod pallet {
             Self::_start_mtc(main, session, deck_emo_base_ids)
         }
 
-        #[pallet::weight(1)]
+        #[pallet::weight(T::WeightInfo::start_mtc_by_session())]
         #[transactional]
         pub fn start_mtc_by_session(
             origin: OriginFor<T>,
@@ -180,7 +266,7 @@ pub mod pallet {
             Self::_start_mtc(main, session, deck_emo_base_ids)
         }
 
-        #[pallet::weight(1)]
+        #[pallet::weight(T::WeightInfo::finish_mtc_shop(player_operations.len() as u32))]
         #[transactional]
         pub fn finish_mtc_shop(
             origin: OriginFor<T>,
@@ -195,6 +281,76 @@ pub mod pallet {
     }
 }
 
+// for the runtime api, nothing is written to the storage
+impl<T: Config> Pallet<T> {
+    // none if the player is not in a mtc
+    pub fn player_view(account_id: &T::AccountId) -> Option<PlayerView<T::AccountId>> {
+        let grade_and_board_history = PlayerGradeAndBoardHistory::<T>::get(account_id)?;
+        let pool = PlayerPool::<T>::get(account_id)?;
+        let (turn, mtc::GradeAndBoard { board, .. }) =
+            get_turn_and_previous_grade_and_board(&grade_and_board_history);
+        let catalog = get_catalog(&pool, &board, PlayerSeed::<T>::get(account_id)?)
+            .map_err(|e| debug!("player_view: {}", e))
+            .ok()?;
+
+        Some(PlayerView {
+            ep: PlayerEp::<T>::get(account_id)?,
+            turn,
+            health: PlayerHealth::<T>::get(account_id)?,
+            grade_and_board_history,
+            upgrade_coin: PlayerUpgradeCoin::<T>::get(account_id),
+            ghosts: PlayerGhosts::<T>::get(account_id)?,
+            ghost_states: PlayerGhostStates::<T>::get(account_id)?,
+            battle_ghost_index: PlayerBattleGhostIndex::<T>::get(account_id)?,
+            pool,
+            catalog,
+        })
+    }
+
+    // (player board grade, ghost board grade)
+    pub fn simulate_battle(
+        board: mtc::Board,
+        ghost_board: mtc::GhostBoard,
+        seed: u64,
+    ) -> Option<(u8, u8)> {
+        let emo_bases = <EmoBases<T>>::get()?;
+
+        march_pvg(&board, &ghost_board, seed, &emo_bases)
+            .map(|(player_board_grade, ghost_board_grade, _)| {
+                (player_board_grade, ghost_board_grade)
+            })
+            .map_err(|e| debug!("simulate_battle: {}", e))
+            .ok()
+    }
+
+    // (board, grade, upgrade coin) after the operations
+    pub fn simulate_shop(
+        board: mtc::Board,
+        mut grade: u8,
+        mut upgrade_coin: Option<u8>,
+        player_operations: Vec<mtc::shop::PlayerOperation>,
+        pool: Vec<mtc::Emo>,
+        seed: u64,
+        turn: u8,
+    ) -> Option<(mtc::Board, u8, Option<u8>)> {
+        let emo_bases = <EmoBases<T>>::get()?;
+
+        verify_player_operations_and_update(
+            board,
+            &mut grade,
+            &mut upgrade_coin,
+            &player_operations,
+            &pool,
+            seed,
+            turn,
+            &emo_bases,
+        )
+        .map(|board| (board, grade, upgrade_coin))
+        .map_err(|e| debug!("simulate_shop: {}", e))
+        .ok()
+    }
+}
+
 impl<T: Config> Pallet<T> {
     fn _start_mtc(
         main: T::AccountId,
@@ -212,15 +368,21 @@ impl<T: Config> Pallet<T> {
         PlayerMainToSession::<T>::insert(&main, &session);
         PlayerSessionToMain::<T>::insert(&session, &main);
 
+        let emo_bases = <EmoBases<T>>::get().ok_or(<Error<T>>::EmoBasesNone)?;
+        let fixed_base_ids =
+            <DeckFixedEmoBaseIds<T>>::get().ok_or(<Error<T>>::FixedEmoBaseIdsNone)?;
+        let built_base_ids =
+            <DeckBuiltEmoBaseIds<T>>::get().ok_or(<Error<T>>::BuiltEmoBaseIdsNone)?;
+
         PlayerHealth::<T>::insert(&main, PLAYER_INITIAL_HEALTH);
         PlayerSeed::<T>::insert(&main, seed);
         PlayerPool::<T>::insert(
             &main,
             build_pool(
                 &deck_emo_base_ids,
-                &<EmoBases<T>>::get().ok_or(<Error<T>>::EmoBasesNone)?,
-                &<DeckFixedEmoBaseIds<T>>::get().ok_or(<Error<T>>::FixedEmoBaseIdsNone)?,
-                &<DeckBuiltEmoBaseIds<T>>::get().ok_or(<Error<T>>::BuiltEmoBaseIdsNone)?,
+                &emo_bases,
+                &fixed_base_ids,
+                &built_base_ids,
             )
             .or(Err(Error::<T>::DeckBuildingFailed))?,
         );
@@ -228,12 +390,22 @@ impl<T: Config> Pallet<T> {
         PlayerBattleGhostIndex::<T>::insert(&main, 0);
         Self::_update_upgrade_coin(&main, get_upgrade_coin(2));
 
-        Self::_matchmake(&main, seed);
+        Self::_matchmake(
+            &main,
+            seed,
+            &emo_bases,
+            &[&built_base_ids[..], &fixed_base_ids[..]].concat(),
+        );
 
         Ok(().into())
     }
 
-    fn _matchmake(account_id: &T::AccountId, seed: u64) {
+    fn _matchmake(
+        account_id: &T::AccountId,
+        seed: u64,
+        emo_bases: &emo::Bases,
+        deck_base_ids: &[u16],
+    ) {
         let ep = if PlayerEp::<T>::contains_key(account_id) {
             PlayerEp::<T>::get(account_id).unwrap()
         } else {
@@ -241,9 +413,35 @@ impl<T: Config> Pallet<T> {
             INITIAL_EP
         };
 
-        let selected = choose_ghosts(ep, seed, &MatchmakingGhosts::<T>::get);
+        let recent_opponents = PlayerRecentOpponents::<T>::get(account_id).unwrap_or_default();
+        let mut excluded_accounts = recent_opponents.clone();
+        excluded_accounts.push(account_id.clone());
+
+        let ghosts = matchmake(
+            &PalletMatchmakingStorage::<T>(PhantomData),
+            ep,
+            seed,
+            &ChooseGhostsOptions {
+                block_number: Self::_get_block_number(),
+                excluded_accounts: &excluded_accounts,
+                upward_band_search_len: T::UpwardBandSearchLen::get(),
+                recency_half_life: T::RecencyHalfLife::get(),
+            },
+            emo_bases,
+            deck_base_ids,
+        );
 
-        PlayerGhosts::<T>::insert(account_id, selected);
+        let ghost_accounts = ghosts.iter().map(|(a, _, _)| a.clone()).collect::<Vec<_>>();
+        PlayerRecentOpponents::<T>::insert(
+            account_id,
+            update_recent_opponents(
+                recent_opponents,
+                &ghost_accounts,
+                T::RecentOpponentsMtcLen::get(),
+            ),
+        );
+
+        PlayerGhosts::<T>::insert(account_id, ghosts);
         PlayerGhostStates::<T>::insert(&account_id, build_initial_ghost_states());
     }
 
@@ -281,9 +479,19 @@ impl<T: Config> Pallet<T> {
             PlayerGhostStates::<T>::get(&account_id).ok_or(<Error<T>>::PlayerGhostStatesNone)?;
 
         let new_seed = Self::_get_random_seed(&b"finish_mtc_shop"[..]);
-        let (ghosts, ghost_eps) = separate_player_ghosts(
-            PlayerGhosts::<T>::get(&account_id).ok_or(<Error<T>>::PlayerGhostsNone)?,
+        let player_ghosts =
+            PlayerGhosts::<T>::get(&account_id).ok_or(<Error<T>>::PlayerGhostsNone)?;
+        let ghost_ratings = get_ghost_ratings(
+            &player_ghosts
+                .iter()
+                .map(|(a, ep, _)| (a.clone(), *ep))
+                .collect::<Vec<_>>(),
+            Self::_get_rd,
         );
+        let ghosts = player_ghosts
+            .into_iter()
+            .map(|(_, _, ghost)| ghost)
+            .collect::<Vec<_>>();
 
         let final_place = Self::_battle(
             &account_id,
@@ -307,7 +515,7 @@ impl<T: Config> Pallet<T> {
             battle_ghost_index,
             health,
             ghost_states,
-            &ghost_eps,
+            &ghost_ratings,
             grade_and_board_history,
             final_place,
         )?;
@@ -411,7 +619,7 @@ impl<T: Config> Pallet<T> {
         battle_ghost_index: u8,
         health: u8,
         ghost_states: Vec<mtc::GhostState>,
-        ghost_eps: &[u16],
+        ghost_ratings: &[Option<Rating>],
         mut grade_and_board_history: Vec<mtc::GradeAndBoard>,
         final_place: Option<u8>,
     ) -> Result<(), Error<T>> {
@@ -422,7 +630,7 @@ impl<T: Config> Pallet<T> {
                 account_id,
                 place,
                 &ghost_states,
-                ghost_eps,
+                ghost_ratings,
                 &grade_and_board_history,
             )?;
         } else {
@@ -446,13 +654,21 @@ impl<T: Config> Pallet<T> {
         account_id: &T::AccountId,
         place: u8,
         ghost_states: &[mtc::GhostState],
-        ghost_eps: &[u16],
+        ghost_ratings: &[Option<Rating>],
         grade_and_board_history: &[mtc::GradeAndBoard],
     ) -> Result<(), Error<T>> {
-        let ep = Self::_update_ep(account_id, place, ghost_states, ghost_eps)?;
+        let ep = Self::_update_ep(account_id, place, ghost_states, ghost_ratings)?;
+
+        register_finished_mtc_ghost(
+            &mut PalletMatchmakingStorage::<T>(PhantomData),
+            account_id.clone(),
+            place,
+            ep,
+            Self::_get_block_number(),
+            grade_and_board_history,
+        );
 
         if place < 4 {
-            Self::_register_ghost(account_id, ep, grade_and_board_history);
             PlayerFirstAirdropEligible::<T>::insert(&account_id, true);
         }
 
@@ -492,52 +708,49 @@ impl<T: Config> Pallet<T> {
         Ok(())
     }
 
-    fn _register_ghost(
-        account_id: &T::AccountId,
-        ep: u16,
-        grade_and_board_history: &[mtc::GradeAndBoard],
-    ) {
-        let (ep_band, ghosts_with_data) =
-            build_matchmaking_ghosts(account_id, ep, grade_and_board_history, &|ep_band| {
-                MatchmakingGhosts::<T>::get(ep_band)
-            });
-
-        MatchmakingGhosts::<T>::insert(ep_band, ghosts_with_data);
+    fn _get_block_number() -> u32 {
+        <frame_system::Module<T>>::block_number().saturated_into()
     }
 
     fn _update_ep(
         account_id: &T::AccountId,
         place: u8,
         ghost_states: &[mtc::GhostState],
-        ghost_eps: &[u16],
+        ghost_ratings: &[Option<Rating>],
     ) -> Result<u16, Error<T>> {
-        let mut ghosts = ghost_states
-            .iter()
-            .map(|s| {
-                if let mtc::GhostState::Active { health: h } = s {
-                    *h
-                } else {
-                    0
-                }
-            })
-            .zip(ghost_eps.to_vec())
-            .collect::<Vec<_>>();
-
-        ghosts.sort_by_key(|&(health, _)| health);
-        ghosts.reverse();
-
-        let sorted_ghost_eps = ghosts.iter().map(|&(_, ep)| ep).collect::<Vec<_>>();
-
-        let new_ep = calculate_new_ep(
-            PlayerEp::<T>::get(account_id).ok_or(<Error<T>>::PlayerEpNone)?,
+        let (rd, periods) = Self::_get_rd_and_periods(account_id);
+        let new_rating = calculate_new_rating(
+            Rating {
+                ep: PlayerEp::<T>::get(account_id).ok_or(<Error<T>>::PlayerEpNone)?,
+                rd,
+            },
             place,
-            &sorted_ghost_eps,
+            &sort_ghost_ratings(ghost_states, ghost_ratings),
+            periods,
         );
+        let new_ep = new_rating.ep;
 
+        PlayerRd::<T>::insert(account_id, (new_rating.rd, Self::_get_block_number()));
         PlayerEp::<T>::insert(account_id, new_ep);
         Ok(new_ep)
     }
 
+    // the rating deviation including the increase by inactivity
+    fn _get_rd(account_id: &T::AccountId) -> u16 {
+        let (rd, periods) = Self::_get_rd_and_periods(account_id);
+        increase_rd(rd, periods)
+    }
+
+    // the stored rating deviation and the rating periods since it was updated
+    fn _get_rd_and_periods(account_id: &T::AccountId) -> (u16, u32) {
+        PlayerRd::<T>::get(account_id).map_or((MAX_RD, 0), |(rd, block_number)| {
+            (
+                rd,
+                Self::_get_block_number().saturating_sub(block_number) / RATING_PERIOD_BLOCKS,
+            )
+        })
+    }
+
     fn _cleanup_finished(main: &T::AccountId) {
         PlayerPool::<T>::remove(main);
         PlayerHealth::<T>::remove(main);
@@ -562,3 +775,23 @@ impl<T: Config> Pallet<T> {
         )
     }
 }
+
+struct PalletMatchmakingStorage<T>(PhantomData<T>);
+
+impl<T: Config> MatchmakingStorage<T::AccountId> for PalletMatchmakingStorage<T> {
+    fn get_ghosts_info(&self, ep_band: u16) -> Option<Vec<(u32, T::AccountId, u16)>> {
+        MatchmakingGhostsInfo::<T>::get(ep_band)
+    }
+
+    fn insert_ghosts_info(&mut self, ep_band: u16, info: Vec<(u32, T::AccountId, u16)>) {
+        MatchmakingGhostsInfo::<T>::insert(ep_band, info);
+    }
+
+    fn get_ghost(&self, ep_band: u16, index: u8) -> Option<mtc::Ghost> {
+        MatchmakingGhostByIndex::<T>::get((ep_band, index))
+    }
+
+    fn insert_ghost(&mut self, ep_band: u16, index: u8, ghost: mtc::Ghost) {
+        MatchmakingGhostByIndex::<T>::insert((ep_band, index), ghost);
+    }
+}
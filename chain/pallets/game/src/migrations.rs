@@ -0,0 +1,54 @@
+use super::*;
+use common::mtc::ghost::MATCHMAKING_GHOSTS_PER_BAND;
+use frame_support::{traits::Get, weights::Weight};
+
+// splits MatchmakingGhosts into MatchmakingGhostsInfo and MatchmakingGhostByIndex,
+// marks the ghosts of the players in a mtc as ghosts with an account,
+// and sets the rating deviation of the rated players
+pub fn migrate_to_v1<T: Config>() -> Weight {
+    // the registered blocks are unknown, the index keeps the oldest first for the ties
+    let block_number = Pallet::<T>::_get_block_number();
+    let mut reads = 0u64;
+    let mut writes = 0u64;
+
+    for (ep_band, ghosts) in MatchmakingGhosts::<T>::drain() {
+        reads += 1;
+        writes += 1;
+
+        let info = ghosts
+            .into_iter()
+            .take(MATCHMAKING_GHOSTS_PER_BAND)
+            .enumerate()
+            .map(|(index, (account, ep, ghost))| {
+                MatchmakingGhostByIndex::<T>::insert((ep_band, index as u8), ghost);
+                writes += 1;
+                (block_number, account, ep)
+            })
+            .collect::<Vec<_>>();
+
+        MatchmakingGhostsInfo::<T>::insert(ep_band, info);
+        writes += 1;
+    }
+
+    PlayerGhosts::<T>::translate::<Vec<(T::AccountId, u16, mtc_Ghost)>, _>(|_, ghosts| {
+        reads += 1;
+        writes += 1;
+        Some(
+            ghosts
+                .into_iter()
+                .map(|(account, ep, ghost)| (Some(account), ep, ghost))
+                .collect(),
+        )
+    });
+
+    // the eps before the rating deviation have no certainty
+    for (account, _) in PlayerEp::<T>::iter() {
+        reads += 2;
+        if !PlayerRd::<T>::contains_key(&account) {
+            PlayerRd::<T>::insert(&account, (MAX_RD, block_number));
+            writes += 1;
+        }
+    }
+
+    T::DbWeight::get().reads_writes(reads, writes)
+}
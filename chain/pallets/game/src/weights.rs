@@ -0,0 +1,82 @@
+//! Weights for pallet_game
+//!
+//! These weights are hand-written estimates, not the output of `make benchmark`,
+//! which needs the node built with `runtime-benchmarks` on the reference hardware.
+//!
+//! The computation of finish_mtc_shop comes from the native bench
+//! `common/rs/benches/finish_mtc_shop.rs` with the worst case inputs,
+//! with a 3x margin for wasm execution:
+//!
+//! - finish_mtc_shop/all: 7.25 ms, so the base is 3 * 7.25 ms ~= 22 ms
+//! - finish_mtc_shop/shop: 24 us for 199 operations, so 3 * 0.12 us ~= 0.35 us per operation
+//!
+//! The other extrinsics are dominated by their storage accesses,
+//! and their computation is a rough estimate.
+
+#![allow(unused_parens)]
+#![allow(unused_imports)]
+
+use frame_support::{
+    traits::Get,
+    weights::{constants::RocksDbWeight, Weight},
+};
+use sp_std::marker::PhantomData;
+
+pub trait WeightInfo {
+    fn update_emo_bases(b: u32) -> Weight;
+    fn start_mtc() -> Weight;
+    fn start_mtc_by_session() -> Weight;
+    fn finish_mtc_shop(o: u32) -> Weight;
+}
+
+pub struct SubstrateWeight<T>(PhantomData<T>);
+impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
+    fn update_emo_bases(b: u32) -> Weight {
+        (50_000_000 as Weight)
+            .saturating_add((1_500_000 as Weight).saturating_mul(b as Weight))
+            .saturating_add(T::DbWeight::get().reads(1 as Weight))
+            .saturating_add(T::DbWeight::get().writes(3 as Weight))
+    }
+    fn start_mtc() -> Weight {
+        (300_000_000 as Weight)
+            .saturating_add(T::DbWeight::get().reads(8 as Weight))
+            .saturating_add(T::DbWeight::get().writes(19 as Weight))
+    }
+    fn start_mtc_by_session() -> Weight {
+        (300_000_000 as Weight)
+            .saturating_add(T::DbWeight::get().reads(9 as Weight))
+            .saturating_add(T::DbWeight::get().writes(19 as Weight))
+    }
+    fn finish_mtc_shop(o: u32) -> Weight {
+        (22_000_000_000 as Weight)
+            .saturating_add((350_000 as Weight).saturating_mul(o as Weight))
+            .saturating_add(T::DbWeight::get().reads(13 as Weight))
+            .saturating_add(T::DbWeight::get().writes(11 as Weight))
+    }
+}
+
+// For backwards compatibility and tests
+impl WeightInfo for () {
+    fn update_emo_bases(b: u32) -> Weight {
+        (50_000_000 as Weight)
+            .saturating_add((1_500_000 as Weight).saturating_mul(b as Weight))
+            .saturating_add(RocksDbWeight::get().reads(1 as Weight))
+            .saturating_add(RocksDbWeight::get().writes(3 as Weight))
+    }
+    fn start_mtc() -> Weight {
+        (300_000_000 as Weight)
+            .saturating_add(RocksDbWeight::get().reads(8 as Weight))
+            .saturating_add(RocksDbWeight::get().writes(19 as Weight))
+    }
+    fn start_mtc_by_session() -> Weight {
+        (300_000_000 as Weight)
+            .saturating_add(RocksDbWeight::get().reads(9 as Weight))
+            .saturating_add(RocksDbWeight::get().writes(19 as Weight))
+    }
+    fn finish_mtc_shop(o: u32) -> Weight {
+        (22_000_000_000 as Weight)
+            .saturating_add((350_000 as Weight).saturating_mul(o as Weight))
+            .saturating_add(RocksDbWeight::get().reads(13 as Weight))
+            .saturating_add(RocksDbWeight::get().writes(11 as Weight))
+    }
+}
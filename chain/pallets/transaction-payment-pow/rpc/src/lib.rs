@@ -0,0 +1,59 @@
+//! RPC interface for the pow quota of the accounts.
+//! QuotaInfo is returned as SCALE encoded bytes.
+
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use parity_scale_codec::{Codec, Encode};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::Bytes;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+use std::{marker::PhantomData, sync::Arc};
+
+pub use pallet_transaction_payment_pow_runtime_api::TransactionPaymentPowApi as TransactionPaymentPowRuntimeApi;
+
+const RUNTIME_ERROR: i64 = 1;
+
+#[rpc]
+pub trait TransactionPaymentPowApi<BlockHash, AccountId> {
+    // returns the encoded QuotaInfo of the next pow transaction of the account
+    #[rpc(name = "transactionPaymentPow_quotaInfo")]
+    fn quota_info(&self, account: AccountId, at: Option<BlockHash>) -> Result<Bytes>;
+}
+
+pub struct TransactionPaymentPow<C, B> {
+    client: Arc<C>,
+    _marker: PhantomData<B>,
+}
+
+impl<C, B> TransactionPaymentPow<C, B> {
+    pub fn new(client: Arc<C>) -> Self {
+        Self {
+            client,
+            _marker: Default::default(),
+        }
+    }
+}
+
+impl<C, Block, AccountId> TransactionPaymentPowApi<<Block as BlockT>::Hash, AccountId>
+    for TransactionPaymentPow<C, Block>
+where
+    Block: BlockT,
+    C: 'static + ProvideRuntimeApi<Block> + HeaderBackend<Block>,
+    C::Api: TransactionPaymentPowRuntimeApi<Block, AccountId>,
+    AccountId: Codec,
+{
+    fn quota_info(&self, account: AccountId, at: Option<<Block as BlockT>::Hash>) -> Result<Bytes> {
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+
+        self.client
+            .runtime_api()
+            .quota_info(&at, account)
+            .map(|q| q.encode().into())
+            .map_err(|e| RpcError {
+                code: ErrorCode::ServerError(RUNTIME_ERROR),
+                message: "Unable to call the runtime api.".into(),
+                data: Some(format!("{:?}", e).into()),
+            })
+    }
+}
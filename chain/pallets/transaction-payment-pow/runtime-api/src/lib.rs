@@ -0,0 +1,11 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use parity_scale_codec::Codec;
+
+pub use pallet_transaction_payment_pow::QuotaInfo;
+
+sp_api::decl_runtime_apis! {
+    pub trait TransactionPaymentPowApi<AccountId> where AccountId: Codec {
+        fn quota_info(account: AccountId) -> QuotaInfo;
+    }
+}
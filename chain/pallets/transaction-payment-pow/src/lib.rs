@@ -3,40 +3,228 @@
 use common::pow;
 use frame_support::{
     debug::native::debug,
-    dispatch::{CallMetadata, GetCallMetadata},
-    traits::{Currency, Get, Imbalance, OnUnbalanced},
-    weights::constants::ExtrinsicBaseWeight,
+    traits::{Currency, Filter, Get, Imbalance, OnUnbalanced},
 };
 use pallet_transaction_payment::{CurrencyAdapter, OnChargeTransaction};
+use parity_scale_codec::{Decode, Encode};
 use sp_runtime::{
-    traits::{CheckedSub, DispatchInfoOf, One, PostDispatchInfoOf},
+    traits::{DispatchInfoOf, One, PostDispatchInfoOf},
     transaction_validity::{InvalidTransaction, TransactionValidityError},
+    RuntimeDebug,
 };
-use sp_std::convert::{TryFrom, TryInto};
+use sp_std::{convert::TryInto, prelude::*};
+
+// bounds the pow history of each account
+pub const MAX_QUOTA_COUNT: u32 = 100;
+
+// covers finish_mtc_shop with the worst case player operations
+pub const MAX_POW_CALL_LEN: usize = 2048;
+
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct QuotaInfo {
+    // pow transactions of the account in the current window
+    pub used: u32,
+    // none if no quota is set
+    pub max_count: Option<u32>,
+    // the threshold for the next pow transaction of the account
+    pub threshold: u32,
+}
+
+// the storage layout of the pallet
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum Releases {
+    V0,
+    // the threshold, the auto adjustment and the quota are on chain
+    V1,
+}
+
+impl Default for Releases {
+    fn default() -> Self {
+        Releases::V0
+    }
+}
 
 pub use pallet::*;
 
+mod migrations;
+
 #[frame_support::pallet]
 pub mod pallet {
+    use super::*;
     use frame_support::pallet_prelude::*;
     use frame_system::pallet_prelude::*;
+    use sp_runtime::traits::Zero;
 
     #[pallet::config]
-    pub trait Config: frame_system::Config + pallet_sudo::Config {}
+    pub trait Config: frame_system::Config + pallet_sudo::Config {
+        // calls that can be paid with a pow solution instead of a fee
+        type PowCallFilter: Filter<<Self as frame_system::Config>::Call>;
+    }
 
     #[pallet::pallet]
     #[pallet::generate_store(pub(super) trait Store)]
     pub struct Pallet<T>(_);
 
+    #[pallet::storage]
+    pub(super) type StorageVersion<T: Config> = StorageValue<_, Releases, ValueQuery>;
+
     #[pallet::storage]
     pub type AccountData<T: Config> =
         StorageMap<_, Blake2_128Concat, T::AccountId, (T::BlockNumber, T::Index)>;
 
+    #[pallet::type_value]
+    pub fn DefaultThreshold() -> u32 {
+        pow::DEFAULT_THRESHOLD
+    }
+    #[pallet::storage]
+    pub type Threshold<T: Config> = StorageValue<_, u32, ValueQuery, DefaultThreshold>;
+
+    // (period, target pow transaction count per period)
+    #[pallet::storage]
+    pub type AutoAdjustment<T: Config> = StorageValue<_, (T::BlockNumber, u32)>;
+    #[pallet::storage]
+    pub type PowTransactionCount<T: Config> = StorageValue<_, u32, ValueQuery>;
+
+    // (window in blocks, max pow transaction count per window,
+    //  count per window without the difficulty multiplier, difficulty multiplier)
+    #[pallet::storage]
+    pub type Quota<T: Config> = StorageValue<_, (T::BlockNumber, u32, u32, u32)>;
+    // the blocks of the recent pow transactions, at most the max count of the quota
+    #[pallet::storage]
+    pub type PowHistory<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, Vec<T::BlockNumber>, ValueQuery>;
+
+    #[pallet::error]
+    pub enum Error<T> {
+        InvalidThreshold,
+        InvalidAutoAdjustment,
+        InvalidQuota,
+    }
+
     #[pallet::hooks]
-    impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {}
+    impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+        fn on_runtime_upgrade() -> Weight {
+            if StorageVersion::<T>::get() == Releases::V0 {
+                let weight = migrations::migrate_to_v1::<T>();
+                StorageVersion::<T>::put(Releases::V1);
+                weight.saturating_add(T::DbWeight::get().writes(1))
+            } else {
+                T::DbWeight::get().reads(1)
+            }
+        }
+
+        fn on_initialize(n: T::BlockNumber) -> Weight {
+            match AutoAdjustment::<T>::get() {
+                Some((period, target)) if (n % period).is_zero() => {
+                    Threshold::<T>::mutate(|threshold| {
+                        *threshold = pow::adjust_threshold(
+                            *threshold,
+                            PowTransactionCount::<T>::take(),
+                            target,
+                        )
+                    });
+                    T::DbWeight::get().reads_writes(3, 2)
+                }
+                _ => T::DbWeight::get().reads(1),
+            }
+        }
+    }
 
     #[pallet::call]
-    impl<T: Config> Pallet<T> {}
+    impl<T: Config> Pallet<T> {
+        #[pallet::weight(T::DbWeight::get().writes(1))]
+        pub fn set_threshold(origin: OriginFor<T>, threshold: u32) -> DispatchResultWithPostInfo {
+            ensure_root(origin)?;
+            ensure!(threshold > 0, Error::<T>::InvalidThreshold);
+
+            Threshold::<T>::put(threshold);
+
+            Ok(().into())
+        }
+
+        // none disables the adjustment
+        #[pallet::weight(T::DbWeight::get().writes(2))]
+        pub fn set_auto_adjustment(
+            origin: OriginFor<T>,
+            auto_adjustment: Option<(T::BlockNumber, u32)>,
+        ) -> DispatchResultWithPostInfo {
+            ensure_root(origin)?;
+
+            if let Some((period, _)) = auto_adjustment {
+                ensure!(!period.is_zero(), Error::<T>::InvalidAutoAdjustment);
+            }
+
+            AutoAdjustment::<T>::set(auto_adjustment);
+            PowTransactionCount::<T>::kill();
+
+            Ok(().into())
+        }
+
+        // none disables the quota, only one pow transaction per block is allowed then
+        #[pallet::weight(T::DbWeight::get().writes(1))]
+        pub fn set_quota(
+            origin: OriginFor<T>,
+            quota: Option<(T::BlockNumber, u32, u32, u32)>,
+        ) -> DispatchResultWithPostInfo {
+            ensure_root(origin)?;
+
+            if let Some((window, max_count, free_count, difficulty_multiplier)) = quota {
+                ensure!(
+                    !window.is_zero()
+                        && max_count > 0
+                        && max_count <= MAX_QUOTA_COUNT
+                        && free_count <= max_count
+                        && difficulty_multiplier > 0,
+                    Error::<T>::InvalidQuota
+                );
+            }
+
+            Quota::<T>::set(quota);
+
+            Ok(().into())
+        }
+    }
+
+    impl<T: Config> Pallet<T> {
+        pub fn quota_info(who: &T::AccountId) -> QuotaInfo {
+            let threshold = Threshold::<T>::get();
+
+            match Quota::<T>::get() {
+                Some((window, max_count, free_count, difficulty_multiplier)) => {
+                    // the state of the next block, where the transaction will be included
+                    let block_num = <frame_system::Module<T>>::block_number() + One::one();
+                    let used = Self::get_recent_pow_blocks(who, window, block_num).len() as u32;
+
+                    QuotaInfo {
+                        used,
+                        max_count: Some(max_count),
+                        threshold: pow::get_quota_threshold(
+                            threshold,
+                            used,
+                            free_count,
+                            difficulty_multiplier,
+                        ),
+                    }
+                }
+                None => QuotaInfo {
+                    used: 0,
+                    max_count: None,
+                    threshold,
+                },
+            }
+        }
+
+        // the sliding window is (block_num - window, block_num]
+        pub(crate) fn get_recent_pow_blocks(
+            who: &T::AccountId,
+            window: T::BlockNumber,
+            block_num: T::BlockNumber,
+        ) -> Vec<T::BlockNumber> {
+            let mut blocks = PowHistory::<T>::get(who);
+            blocks.retain(|&b| b + window > block_num);
+            blocks
+        }
+    }
 }
 
 type NegativeImbalanceOf<C, T> =
@@ -60,7 +248,7 @@ where
     >,
     OU: OnUnbalanced<NegativeImbalanceOf<C, T>>,
     T::AccountId: AsRef<[u8; 32]>,
-    <T as frame_system::Config>::Call: GetCallMetadata,
+    <T as frame_system::Config>::Call: Encode,
 {
     type LiquidityInfo = Option<NegativeImbalanceOf<C, T>>;
     type Balance = Balance<T, C>;
@@ -85,8 +273,7 @@ where
             }
             PaymentKind::Pow(solution) => {
                 debug!("Pow: Pow ({})", solution);
-                validate_pow::<T, C>(who, &call.get_call_metadata(), fee, tip, solution)
-                    .map_err(|_| InvalidTransaction::Payment)?;
+                validate_pow::<T>(who, call, solution).map_err(|_| InvalidTransaction::Payment)?;
                 Ok(None)
             }
         }
@@ -143,34 +330,29 @@ fn which_payment_kind<T: Config, C: Currency<<T as frame_system::Config>::Accoun
     })
 }
 
-fn validate_pow<T: Config, C: Currency<<T as frame_system::Config>::AccountId>>(
+// pallet index and call index, the first 2 bytes of the encoded call
+fn get_call_index<C: Encode>(call: &C) -> [u8; 2] {
+    call.using_encoded(|e| [e[0], e[1]])
+}
+
+// the fee includes the weight of the call, so the length is checked on the call itself
+fn validate_pow<T: Config>(
     who: &T::AccountId,
-    call_metadata: &CallMetadata,
-    fee: Balance<T, C>,
-    tip: Balance<T, C>,
+    call: &<T as frame_system::Config>::Call,
     solution: u64,
 ) -> Result<(), ()>
 where
     T::AccountId: AsRef<[u8; 32]>,
+    <T as frame_system::Config>::Call: Encode,
 {
-    let almostlen = get_almostlen::<T, C>(fee, tip)?;
-    if almostlen > 1500 {
-        debug!("Pow: too big len: {}", almostlen);
+    let len = call.encoded_size();
+    if len > MAX_POW_CALL_LEN {
+        debug!("Pow: too big len: {}", len);
         return Err(());
     }
 
-    let pallet_name = call_metadata.pallet_name;
-    let function_name = call_metadata.function_name;
-    if !["Game", "FirstAirdrop"].contains(&pallet_name)
-        || ![
-            "start_mtc",
-            "start_mtc_by_session",
-            "finish_mtc_shop",
-            "claim",
-        ]
-        .contains(&function_name)
-    {
-        debug!("Pow: not allowed call: {}, {}", pallet_name, function_name);
+    if !T::PowCallFilter::filter(call) {
+        debug!("Pow: not allowed call: {:?}", get_call_index(call));
         return Err(());
     }
 
@@ -183,33 +365,45 @@ where
         return Err(());
     }
 
-    if !pow::check_solution(who.as_ref(), count.try_into().map_err(|_| ())?, solution) {
+    let quota = <Pallet<T> as Store>::Quota::get();
+    let threshold = <Pallet<T> as Store>::Threshold::get();
+
+    let (threshold, recent_pow_blocks) = match quota {
+        Some((window, max_count, free_count, difficulty_multiplier)) => {
+            let blocks = Pallet::<T>::get_recent_pow_blocks(who, window, current_block_num);
+            let used = blocks.len() as u32;
+
+            if used >= max_count {
+                debug!("Pow: quota exceeded: {}", used);
+                return Err(());
+            }
+
+            (
+                pow::get_quota_threshold(threshold, used, free_count, difficulty_multiplier),
+                Some(blocks),
+            )
+        }
+        None => (threshold, None),
+    };
+
+    if !pow::check_solution(
+        who.as_ref(),
+        count.try_into().map_err(|_| ())?,
+        &call.using_encoded(pow::get_call_hash),
+        solution,
+        threshold,
+    ) {
         debug!("Pow: invalid solution");
         return Err(());
     }
 
     <Pallet<T> as Store>::AccountData::insert(who, (current_block_num, count + One::one()));
+    if let Some(mut blocks) = recent_pow_blocks {
+        blocks.push(current_block_num);
+        <Pallet<T> as Store>::PowHistory::insert(who, blocks);
+    }
+    if <Pallet<T> as Store>::AutoAdjustment::exists() {
+        <Pallet<T> as Store>::PowTransactionCount::mutate(|c| *c = c.saturating_add(1));
+    }
     Ok(())
 }
-
-fn get_almostlen<T: Config, C: Currency<<T as frame_system::Config>::AccountId>>(
-    fee: Balance<T, C>,
-    tip: Balance<T, C>,
-) -> Result<u128, ()> {
-    let base = Balance::<T, C>::try_from(ExtrinsicBaseWeight::get()).map_err(|_| ())?;
-    let almostlen: u128 = fee
-        .checked_sub(&base)
-        .ok_or_else(|| {
-            debug!("Pow: invalid fee");
-        })?
-        .checked_sub(&tip)
-        .ok_or_else(|| {
-            debug!("Pow: tip is bigger than tip");
-        })?
-        .try_into()
-        .map_err(|_| {
-            debug!("Pow: failed: Balance => u128");
-        })?;
-
-    Ok(almostlen)
-}
@@ -0,0 +1,10 @@
+use super::*;
+use frame_support::weights::Weight;
+
+// stores the threshold which was a constant before. the auto adjustment and the quota
+// start unset, so the pow transactions are validated as before until root sets them
+pub fn migrate_to_v1<T: Config>() -> Weight {
+    Threshold::<T>::put(pow::DEFAULT_THRESHOLD);
+
+    T::DbWeight::get().writes(1)
+}
@@ -6,6 +6,7 @@
 #[cfg(feature = "std")]
 include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
 
+use pallet_game_runtime_api::codec_types::mtc;
 use pallet_grandpa::fg_primitives;
 use pallet_grandpa::{AuthorityId as GrandpaId, AuthorityList as GrandpaAuthorityList};
 use sp_api::impl_runtime_apis;
@@ -27,7 +28,7 @@ use sp_version::RuntimeVersion;
 // A few exports that help ease life for downstream crates.
 pub use frame_support::{
     construct_runtime, parameter_types,
-    traits::{Get, KeyOwnerProofSystem, Randomness},
+    traits::{Filter, Get, KeyOwnerProofSystem, Randomness},
     weights::{
         constants::{BlockExecutionWeight, ExtrinsicBaseWeight, RocksDbWeight, WEIGHT_PER_SECOND},
         IdentityFee, Weight,
@@ -94,7 +95,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
     spec_name: create_runtime_str!("open-emoji-battler"),
     impl_name: create_runtime_str!("open-emoji-battler"),
     authoring_version: 1,
-    spec_version: 11,
+    spec_version: 13,
     impl_version: 1,
     apis: RUNTIME_API_VERSIONS,
     transaction_version: 1,
@@ -250,11 +251,40 @@ impl pallet_sudo::Config for Runtime {
     type Call = Call;
 }
 
-impl pallet_transaction_payment_pow::Config for Runtime {}
+// matches the call variants, i.e. the call indices
+pub struct PowCallFilter;
+impl Filter<Call> for PowCallFilter {
+    fn filter(call: &Call) -> bool {
+        matches!(
+            call,
+            Call::Game(pallet_game::Call::start_mtc(..))
+                | Call::Game(pallet_game::Call::start_mtc_by_session(..))
+                | Call::Game(pallet_game::Call::finish_mtc_shop(..))
+                | Call::FirstAirdrop(pallet_first_airdrop::Call::claim(..))
+        )
+    }
+}
+
+impl pallet_transaction_payment_pow::Config for Runtime {
+    type PowCallFilter = PowCallFilter;
+}
 
-impl pallet_game::Config for Runtime {}
+parameter_types! {
+    pub const RecentOpponentsMtcLen: u8 = 3;
+    pub const UpwardBandSearchLen: u16 = 2;
+    pub const RecencyHalfLife: BlockNumber = 7 * DAYS;
+}
 
-impl pallet_first_airdrop::Config for Runtime {}
+impl pallet_game::Config for Runtime {
+    type RecentOpponentsMtcLen = RecentOpponentsMtcLen;
+    type UpwardBandSearchLen = UpwardBandSearchLen;
+    type RecencyHalfLife = RecencyHalfLife;
+    type WeightInfo = pallet_game::weights::SubstrateWeight<Runtime>;
+}
+
+impl pallet_first_airdrop::Config for Runtime {
+    type WeightInfo = pallet_first_airdrop::weights::SubstrateWeight<Runtime>;
+}
 
 // Create the runtime by composing the FRAME pallets that were previously configured.
 construct_runtime!(
@@ -442,6 +472,39 @@ impl_runtime_apis! {
         }
     }
 
+    impl pallet_transaction_payment_pow_runtime_api::TransactionPaymentPowApi<Block, AccountId>
+        for Runtime {
+        fn quota_info(account: AccountId) -> pallet_transaction_payment_pow::QuotaInfo {
+            TransactionPaymentPow::quota_info(&account)
+        }
+    }
+
+    impl pallet_game_runtime_api::GameApi<Block, AccountId> for Runtime {
+        fn player_view(account: AccountId) -> Option<pallet_game::PlayerView<AccountId>> {
+            Game::player_view(&account)
+        }
+
+        fn simulate_battle(
+            board: mtc::Board,
+            ghost_board: mtc::GhostBoard,
+            seed: u64,
+        ) -> Option<(u8, u8)> {
+            Game::simulate_battle(board, ghost_board, seed)
+        }
+
+        fn simulate_shop(
+            board: mtc::Board,
+            grade: u8,
+            upgrade_coin: Option<u8>,
+            player_operations: Vec<mtc::shop::PlayerOperation>,
+            pool: Vec<mtc::Emo>,
+            seed: u64,
+            turn: u8,
+        ) -> Option<(mtc::Board, u8, Option<u8>)> {
+            Game::simulate_shop(board, grade, upgrade_coin, player_operations, pool, seed, turn)
+        }
+    }
+
     #[cfg(feature = "runtime-benchmarks")]
     impl frame_benchmarking::Benchmark<Block> for Runtime {
         fn dispatch_benchmark(
@@ -476,9 +539,68 @@ impl_runtime_apis! {
             add_benchmark!(params, batches, frame_system, SystemBench::<Runtime>);
             add_benchmark!(params, batches, pallet_balances, Balances);
             add_benchmark!(params, batches, pallet_timestamp, Timestamp);
+            add_benchmark!(params, batches, pallet_game, Game);
+            add_benchmark!(params, batches, pallet_first_airdrop, FirstAirdrop);
 
             if batches.is_empty() { return Err("Benchmark not found for this pallet.".into()) }
             Ok(batches)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codec::Encode;
+    use common::{mtc::worst_case, pow};
+    use frame_support::weights::GetDispatchInfo;
+    use pallet_transaction_payment::ChargeTransactionPayment;
+    use sp_runtime::traits::SignedExtension;
+
+    // the signature, the address and the signed extensions of an extrinsic
+    const SIGNED_EXTRINSIC_OVERHEAD: usize = 150;
+
+    fn new_test_ext() -> sp_io::TestExternalities {
+        let mut ext: sp_io::TestExternalities = frame_system::GenesisConfig::default()
+            .build_storage::<Runtime>()
+            .unwrap()
+            .into();
+        ext.execute_with(|| System::set_block_number(1));
+        ext
+    }
+
+    fn validate_pow(who: &AccountId, call: &Call, solution: u64) -> TransactionValidity {
+        // the head bit of the tip marks the pow payment
+        let tip: Balance = (1 << 127) | solution as Balance;
+
+        ChargeTransactionPayment::<Runtime>::from(tip).validate(
+            who,
+            call,
+            &call.get_dispatch_info(),
+            call.encoded_size() + SIGNED_EXTRINSIC_OVERHEAD,
+        )
+    }
+
+    // the weight is in the fee, so a heavy call must not look too long
+    #[test]
+    fn validate_pow_finish_mtc_shop_worst_case() {
+        new_test_ext().execute_with(|| {
+            let who = AccountId::from([1u8; 32]);
+            let call = Call::Game(pallet_game::Call::finish_mtc_shop(
+                worst_case::build_player_operations(),
+            ));
+            assert!(call.encoded_size() <= pallet_transaction_payment_pow::MAX_POW_CALL_LEN);
+
+            let account: &[u8; 32] = who.as_ref();
+            let call_hash = call.using_encoded(pow::get_call_hash);
+            let solution = pow::solve(account, 0, &call_hash, pow::DEFAULT_THRESHOLD);
+
+            let invalid = (solution + 1..)
+                .find(|&s| !pow::check_solution(account, 0, &call_hash, s, pow::DEFAULT_THRESHOLD))
+                .unwrap();
+
+            assert!(validate_pow(&who, &call, invalid).is_err());
+            assert!(validate_pow(&who, &call, solution).is_ok());
+        });
+    }
+}
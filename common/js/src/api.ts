@@ -14,6 +14,54 @@ const registry = new TypeRegistry()
 const types = buildTypes()
 registry.register(types)
 
+// codec types are passed and returned as scale encoded bytes
+const rpc = {
+  game: {
+    playerView: {
+      description: "Returns the state of the player in a mtc.",
+      params: [
+        { name: "account", type: "AccountId" },
+        { name: "at", type: "BlockHash", isOptional: true },
+      ],
+      type: "Option<game_PlayerView>",
+    },
+    simulateBattle: {
+      description: "Returns the encoded (player board grade, ghost board grade).",
+      params: [
+        { name: "board", type: "Bytes" },
+        { name: "ghostBoard", type: "Bytes" },
+        { name: "seed", type: "u64" },
+        { name: "at", type: "BlockHash", isOptional: true },
+      ],
+      type: "Bytes",
+    },
+    simulateShop: {
+      description: "Returns the encoded (board, grade, upgrade coin) after the operations.",
+      params: [
+        { name: "board", type: "Bytes" },
+        { name: "grade", type: "u8" },
+        { name: "upgradeCoin", type: "Option<u8>" },
+        { name: "playerOperations", type: "Bytes" },
+        { name: "pool", type: "Bytes" },
+        { name: "seed", type: "u64" },
+        { name: "turn", type: "u8" },
+        { name: "at", type: "BlockHash", isOptional: true },
+      ],
+      type: "Bytes",
+    },
+  },
+  transactionPaymentPow: {
+    quotaInfo: {
+      description: "Returns the pow quota and the threshold of the next pow transaction.",
+      params: [
+        { name: "account", type: "AccountId" },
+        { name: "at", type: "BlockHash", isOptional: true },
+      ],
+      type: "transactionPaymentPow_QuotaInfo",
+    },
+  },
+}
+
 export const connected = async <T>(
   endpoint: string,
   f: (api: ApiPromise) => Promise<T>,
@@ -39,6 +87,7 @@ export const connect = (endpoint: string, withTypes = true) => {
       provider,
       registry,
       types,
+      rpc,
     })
   } else {
     return ApiPromise.create({
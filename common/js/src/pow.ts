@@ -1,17 +1,29 @@
 import { decodeAddress } from "@polkadot/util-crypto"
 
 import { getWasmSolver } from "./pow/wasm"
-import { solvePowPure, SolverFn, getPowWasmBytes, getPowSolutionBNFromBytes } from "./utils"
+import {
+  solvePowPure,
+  SolverFn,
+  getPowWasmBytes,
+  getPowSolutionBNFromBytes,
+  getPowCallHash,
+} from "./utils"
 
 let solver: SolverFn | null = null
-export const solvePow = async (address: string, count: number) => {
+export const solvePow = async (
+  address: string,
+  count: number,
+  // the scale encoded call
+  call: Uint8Array,
+  threshold: number
+) => {
   if (!solver) {
     const mod = await WebAssembly.compile(getPowWasmBytes())
     solver = await getWasmSolver(mod)
   }
 
   const publicKey = decodeAddress(address)
-  const solution = getPowSolutionBNFromBytes(solvePowPure(solver, publicKey, count))
+  const solution = getPowSolutionBNFromBytes(solvePowPure(solver, publicKey, count, getPowCallHash(call), threshold))
 
   return solution
 }
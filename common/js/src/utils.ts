@@ -4,6 +4,7 @@ import type { RegistryTypes } from "@polkadot/types/types/registry"
 import type { SignerOptions } from "@polkadot/api/submittable/types"
 import type { Signer } from "@polkadot/api/types"
 import type { IKeyringPair } from "@polkadot/types/types"
+import { blake2AsU8a } from "@polkadot/util-crypto"
 
 import { decode } from "./pow/base64"
 import { base64 } from "./pow/optimized.wrap"
@@ -13,6 +14,25 @@ import * as definitions from "./interfaces/definitions"
 export const buildTypes = () => {
   let types: RegistryTypes = {
     AccountInfo: "AccountInfoWithDualRefCount",
+    // pallet_game::PlayerView
+    game_PlayerView: {
+      ep: "u16",
+      turn: "u8",
+      health: "u8",
+      grade_and_board_history: "Vec<mtc_GradeAndBoard>",
+      upgrade_coin: "Option<u8>",
+      ghosts: "Vec<(Option<AccountId>, u16, mtc_Ghost)>",
+      ghost_states: "Vec<mtc_GhostState>",
+      battle_ghost_index: "u8",
+      pool: "Vec<mtc_Emo>",
+      catalog: "mtc_shop_Catalog",
+    },
+    // pallet_transaction_payment_pow::QuotaInfo
+    transactionPaymentPow_QuotaInfo: {
+      used: "u32",
+      max_count: "Option<u32>",
+      threshold: "u32",
+    },
   }
   for (const [n, t] of Object.values(definitions).flatMap((d) => Object.entries(d.types))) {
     types[n] = t
@@ -39,20 +59,27 @@ export const extractTxArgs = (account: KeyringPairOrAddressAndSigner, powSolutio
   return [pairOrAddress, options] as const
 }
 
-const PowThreshold = 2655
 export const solvePowByBytes = async (
   wasmBytes: Uint8Array,
   publicKey: Uint8Array,
-  count: number
+  count: number,
+  callHash: Uint8Array,
+  threshold: number
 ) => {
   const mod = await WebAssembly.compile(wasmBytes)
   const solver = await getWasmSolver(mod)
 
-  return solvePowPure(solver, publicKey, count)
+  return solvePowPure(solver, publicKey, count, callHash, threshold)
 }
 
 export type SolverFn = (buffer: Uint8Array, threshold: number) => Uint8Array[]
-export const solvePowPure = (solver: SolverFn, publicKey: Uint8Array, count: number) => {
+export const solvePowPure = (
+  solver: SolverFn,
+  publicKey: Uint8Array,
+  count: number,
+  callHash: Uint8Array,
+  threshold: number
+) => {
   const buffer = new Uint8Array(128)
 
   buffer.set(publicKey, 0)
@@ -62,10 +89,12 @@ export const solvePowPure = (solver: SolverFn, publicKey: Uint8Array, count: num
   const key = new ArrayBuffer(4)
   new DataView(key).setUint32(0, 123, true)
   buffer.set(new Uint8Array(key), 36)
+  // binds the solution to the call and its args
+  buffer.set(callHash.subarray(0, 32), 37)
 
   const label = `pow (count: ${count}, publicKey: ${publicKey.toString()})`
   console.time(label)
-  const [s, hash] = solver(buffer, PowThreshold)
+  const [s, hash] = solver(buffer, threshold)
   console.timeEnd(label)
   if (hash.length === 0) {
     throw new Error(`Internal error or no solution found: ${label}`)
@@ -74,6 +103,9 @@ export const solvePowPure = (solver: SolverFn, publicKey: Uint8Array, count: num
   return s.slice(-8)
 }
 
+// pow::get_call_hash, the hash of the scale encoded call
+export const getPowCallHash = (encodedCall: Uint8Array) => blake2AsU8a(encodedCall, 256)
+
 export const getPowWasmBytes = () => decode(base64)
 export const getPowSolutionBNFromBytes = (solution: Uint8Array) => new BN(solution, undefined, "le")
 
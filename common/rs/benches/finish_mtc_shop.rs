@@ -0,0 +1,85 @@
+use common::{
+    codec_types::*,
+    mtc::{
+        battle::organizer::battle_all, setup::build_initial_ghost_states, setup::build_pool,
+        shop::player_operation::verify_player_operations_and_update, worst_case,
+    },
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use parity_scale_codec::{Decode, Encode};
+
+// the computation of finish_mtc_shop in the contract with the worst case inputs
+fn criterion_benchmark(c: &mut Criterion) {
+    let emo_bases = worst_case::build_emo_bases();
+    let (built, fixed) = worst_case::build_deck_base_ids();
+    let pool = build_pool(&built, &emo_bases, &fixed, &built).unwrap();
+    let history = worst_case::build_grade_and_board_history(&emo_bases);
+    let ghost = worst_case::build_ghost(&emo_bases);
+    let ghosts = vec![ghost.clone(), ghost.clone(), ghost];
+    let ops = worst_case::build_player_operations();
+    let turn = history.len() as u8 + 1;
+    let previous = history.last().unwrap().clone();
+
+    let shop = || {
+        let mut grade = previous.grade;
+        let board = verify_player_operations_and_update(
+            previous.board.clone(),
+            &mut grade,
+            &mut None,
+            &ops,
+            &pool,
+            1,
+            turn,
+            &emo_bases,
+        )
+        .unwrap();
+        (grade, board)
+    };
+    let battle = |grade: u8, board: &mtc::Board| {
+        let mut health = 30;
+        let mut ghost_states = build_initial_ghost_states();
+        battle_all(
+            board,
+            &mut health,
+            &mut ghost_states,
+            grade,
+            &ghosts,
+            0,
+            turn,
+            2,
+            &emo_bases,
+        )
+        .unwrap()
+    };
+
+    // the values finish_mtc_shop reads from and writes to the contract storage
+    let storage = || {
+        let immutable = (&pool, &ghosts).encode();
+        let mutable = history.encode();
+        (
+            <(Vec<mtc::Emo>, Vec<mtc::Ghost>)>::decode(&mut &immutable[..]).unwrap(),
+            <Vec<mtc::GradeAndBoard>>::decode(&mut &mutable[..]).unwrap(),
+        )
+    };
+
+    c.bench_function("finish_mtc_shop/storage", |b| {
+        b.iter(|| black_box(storage()))
+    });
+
+    c.bench_function("finish_mtc_shop/shop", |b| b.iter(|| black_box(shop())));
+
+    let (grade, board) = shop();
+    c.bench_function("finish_mtc_shop/battle", |b| {
+        b.iter(|| black_box(battle(grade, &board)))
+    });
+
+    c.bench_function("finish_mtc_shop/all", |b| {
+        b.iter(|| {
+            let (grade, board) = shop();
+            black_box(battle(grade, &board))
+        })
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
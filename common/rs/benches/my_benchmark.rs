@@ -1,15 +1,50 @@
 use common::pow;
 use criterion::{criterion_group, criterion_main, Criterion};
-use rand::{thread_rng, Rng};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+use std::sync::atomic::AtomicBool;
 
 fn criterion_benchmark(c: &mut Criterion) {
-    let mut rng = thread_rng();
+    let mut rng = Pcg64Mcg::seed_from_u64(0);
     let mut account = [0u8; 32];
 
+    // the same 2^16 solutions by the one-shot hash and the puzzle
+    rng.fill(&mut account[..]);
+    c.bench_function("pow::check_solution x 65536", |b| {
+        b.iter(|| {
+            (0..1 << 16)
+                .filter(|&s| pow::check_solution(&account, 1, &[0; 32], s, pow::DEFAULT_THRESHOLD))
+                .count()
+        })
+    });
+
+    let puzzle = pow::Puzzle::new(&account, 1, &[0; 32], pow::DEFAULT_THRESHOLD);
+    c.bench_function("pow::Puzzle::check x 65536", |b| {
+        b.iter(|| (0..1 << 16).filter(|&s| puzzle.check(s)).count())
+    });
+
     c.bench_function("pow::solve", |b| {
         b.iter(|| {
             rng.fill(&mut account[..]);
-            pow::solve(&account, rng.gen())
+            pow::solve(&account, rng.gen(), &[0; 32], pow::DEFAULT_THRESHOLD)
+        })
+    });
+
+    let threads = pow::get_solve_threads();
+    let cancelled = AtomicBool::new(false);
+
+    c.bench_function(&format!("pow::solve_parallel ({} threads)", threads), |b| {
+        b.iter(|| {
+            rng.fill(&mut account[..]);
+            pow::solve_parallel(
+                &account,
+                rng.gen(),
+                &[0; 32],
+                pow::DEFAULT_THRESHOLD,
+                threads,
+                &|_| {},
+                &cancelled,
+            )
         })
     });
 }
@@ -1,3 +1,4 @@
+pub mod achievement;
 pub mod battle;
 pub mod decoders;
 pub mod emo_bases;
@@ -7,3 +8,5 @@ pub mod ghost;
 pub mod setup;
 pub mod shop;
 pub mod utils;
+#[cfg(any(feature = "std", feature = "runtime-benchmarks"))]
+pub mod worst_case;
@@ -0,0 +1,320 @@
+use crate::{codec_types::*, mtc::utils::BOARD_EMO_MAX_COUNT};
+use sp_std::prelude::*;
+
+// an achievement is earned when all the conditions hold at the end of an mtc
+pub struct Achievement {
+    pub id: u16,
+    pub conditions: &'static [Condition],
+}
+
+pub enum Condition {
+    // the place is at most max
+    Place { max: u8 },
+    // every emo on the final board is the typ
+    OnlyTyp(emo::Typ),
+    // the number of emos on the final board
+    BoardEmoCount { min: u8 },
+    // the distinct triples on the boards of the mtc
+    TripleCount { min: u8 },
+    // the grade reached by the turn
+    GradeByTurn { grade: u8, turn: u8 },
+    // a single damage in a battle, needs the battle logs
+    BattleDamage { min: u16 },
+}
+
+// the ids are stored, never reuse them
+pub const ACHIEVEMENTS: &[Achievement] = &[
+    Achievement {
+        id: 1,
+        conditions: &[Condition::Place { max: 1 }],
+    },
+    Achievement {
+        id: 2,
+        conditions: &[
+            Condition::Place { max: 1 },
+            Condition::OnlyTyp(emo::Typ::Human),
+        ],
+    },
+    Achievement {
+        id: 3,
+        conditions: &[
+            Condition::Place { max: 1 },
+            Condition::OnlyTyp(emo::Typ::Nature),
+        ],
+    },
+    Achievement {
+        id: 4,
+        conditions: &[
+            Condition::Place { max: 1 },
+            Condition::OnlyTyp(emo::Typ::Food),
+        ],
+    },
+    Achievement {
+        id: 5,
+        conditions: &[
+            Condition::Place { max: 1 },
+            Condition::OnlyTyp(emo::Typ::Object),
+        ],
+    },
+    Achievement {
+        id: 6,
+        conditions: &[
+            Condition::Place { max: 1 },
+            Condition::BoardEmoCount {
+                min: BOARD_EMO_MAX_COUNT,
+            },
+        ],
+    },
+    Achievement {
+        id: 7,
+        conditions: &[Condition::TripleCount { min: 3 }],
+    },
+    Achievement {
+        id: 8,
+        conditions: &[Condition::GradeByTurn { grade: 6, turn: 7 }],
+    },
+    Achievement {
+        id: 9,
+        conditions: &[Condition::BattleDamage { min: 50 }],
+    },
+];
+
+impl Achievement {
+    pub fn needs_battle_logs(&self) -> bool {
+        self.conditions
+            .iter()
+            .any(|c| matches!(c, Condition::BattleDamage { .. }))
+    }
+}
+
+// the achievements on the battle logs are excluded where the logs are not collected
+pub fn get_earnable_achievements(
+    has_battle_logs: bool,
+) -> impl Iterator<Item = &'static Achievement> {
+    ACHIEVEMENTS
+        .iter()
+        .filter(move |a| has_battle_logs || !a.needs_battle_logs())
+}
+
+pub struct Input<'a> {
+    pub place: u8,
+    pub grade_and_board_history: &'a [mtc::GradeAndBoard],
+    // none where the logs are not collected
+    pub battle_logs: Option<&'a [mtc::battle::Logs]>,
+    pub emo_bases: &'a emo::Bases,
+}
+
+// the ids of the achievements earned, except already_earned
+pub fn evaluate_achievements(input: &Input, already_earned: &[u16]) -> Vec<u16> {
+    get_earnable_achievements(input.battle_logs.is_some())
+        .filter(|a| !already_earned.contains(&a.id))
+        .filter(|a| a.conditions.iter().all(|c| is_satisfied(c, input)))
+        .map(|a| a.id)
+        .collect()
+}
+
+fn is_satisfied(condition: &Condition, input: &Input) -> bool {
+    let final_board = input
+        .grade_and_board_history
+        .last()
+        .map(|h| &h.board.0[..])
+        .unwrap_or(&[]);
+
+    match condition {
+        Condition::Place { max } => input.place <= *max,
+        Condition::OnlyTyp(typ) => {
+            !final_board.is_empty()
+                && final_board.iter().all(|e| {
+                    input
+                        .emo_bases
+                        .find(e.base_id)
+                        .map_or(false, |b| &b.typ == typ)
+                })
+        }
+        Condition::BoardEmoCount { min } => final_board.len() >= *min as usize,
+        Condition::TripleCount { min } => {
+            count_triples_made(input.grade_and_board_history) >= *min as usize
+        }
+        Condition::GradeByTurn { grade, turn } => input
+            .grade_and_board_history
+            .iter()
+            .take(*turn as usize)
+            .any(|h| h.grade >= *grade),
+        Condition::BattleDamage { min } => input.battle_logs.map_or(false, |logs| {
+            logs.iter()
+                .flat_map(|l| l.0.iter())
+                .any(|l| matches!(l, mtc::battle::Log::Damage { damage, .. } if damage >= min))
+        }),
+    }
+}
+
+// a triple keeps its mtc_emo_ids while it's on the board
+fn count_triples_made(grade_and_board_history: &[mtc::GradeAndBoard]) -> usize {
+    let mut triples = grade_and_board_history
+        .iter()
+        .flat_map(|h| h.board.0.iter())
+        .filter(|e| e.attributes.is_triple)
+        .map(|e| &e.mtc_emo_ids)
+        .collect::<Vec<_>>();
+
+    triples.sort_unstable();
+    triples.dedup();
+    triples.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_emo_bases() -> emo::Bases {
+        let mut bases = emo::Bases::new();
+        for (id, typ) in [
+            (1, emo::Typ::Food),
+            (2, emo::Typ::Food),
+            (3, emo::Typ::Human),
+        ] {
+            bases.add(emo::Base {
+                id,
+                typ,
+                ..Default::default()
+            });
+        }
+        bases
+    }
+
+    fn build_board(emos: &[(u16, bool)]) -> mtc::Board {
+        mtc::Board(
+            emos.iter()
+                .map(|&(base_id, is_triple)| mtc::BoardEmo {
+                    mtc_emo_ids: vec![base_id],
+                    base_id,
+                    attributes: emo::Attributes {
+                        is_triple,
+                        ..Default::default()
+                    },
+                })
+                .collect(),
+        )
+    }
+
+    fn build_history(grades_and_boards: &[(u8, &[(u16, bool)])]) -> Vec<mtc::GradeAndBoard> {
+        grades_and_boards
+            .iter()
+            .map(|&(grade, emos)| mtc::GradeAndBoard {
+                grade,
+                board: build_board(emos),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ids_unique() {
+        let mut ids = ACHIEVEMENTS.iter().map(|a| a.id).collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), ACHIEVEMENTS.len());
+    }
+
+    #[test]
+    fn test_evaluate_achievements() {
+        let emo_bases = build_emo_bases();
+        let history = build_history(&[(1, &[(1, false)]), (2, &[(1, false), (2, false)])]);
+        let mut input = Input {
+            place: 1,
+            grade_and_board_history: &history,
+            battle_logs: None,
+            emo_bases: &emo_bases,
+        };
+
+        assert_eq!(evaluate_achievements(&input, &[]), vec![1, 4]);
+        assert_eq!(evaluate_achievements(&input, &[1]), vec![4]);
+
+        input.place = 2;
+        assert_eq!(evaluate_achievements(&input, &[]), Vec::<u16>::new());
+
+        let history = build_history(&[(1, &[(1, false), (3, false)])]);
+        input.place = 1;
+        input.grade_and_board_history = &history;
+        assert_eq!(evaluate_achievements(&input, &[]), vec![1]);
+
+        input.grade_and_board_history = &[];
+        assert_eq!(evaluate_achievements(&input, &[]), vec![1]);
+    }
+
+    #[test]
+    fn test_triple_count() {
+        let emo_bases = build_emo_bases();
+        // a triple is sold and another one is made in the third turn
+        let history = build_history(&[
+            (1, &[(1, true)]),
+            (2, &[(1, true), (2, true)]),
+            (2, &[(2, true), (3, true)]),
+            (3, &[(2, true)]),
+        ]);
+        assert_eq!(count_triples_made(&history), 3);
+
+        let input = Input {
+            place: 4,
+            grade_and_board_history: &history,
+            battle_logs: None,
+            emo_bases: &emo_bases,
+        };
+        assert_eq!(evaluate_achievements(&input, &[]), vec![7]);
+    }
+
+    #[test]
+    fn test_grade_by_turn() {
+        let emo_bases = build_emo_bases();
+        let evaluate = |history: &[mtc::GradeAndBoard]| {
+            evaluate_achievements(
+                &Input {
+                    place: 4,
+                    grade_and_board_history: history,
+                    battle_logs: None,
+                    emo_bases: &emo_bases,
+                },
+                &[],
+            )
+        };
+
+        let mut history = build_history(&[(6, &[][..]); 8]);
+        assert_eq!(evaluate(&history), vec![8]);
+
+        for h in history.iter_mut().take(7) {
+            h.grade = 5;
+        }
+        assert_eq!(evaluate(&history), Vec::<u16>::new());
+    }
+
+    #[test]
+    fn test_get_earnable_achievements() {
+        let ids = |has_battle_logs| {
+            get_earnable_achievements(has_battle_logs)
+                .map(|a| a.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(true), (1..=9).collect::<Vec<_>>());
+        assert_eq!(ids(false), (1..=8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_battle_damage() {
+        let emo_bases = build_emo_bases();
+        let logs = vec![mtc::battle::Logs(vec![mtc::battle::Log::Damage {
+            player_index: 0,
+            emo_index: 0,
+            damage: 50,
+            health: 0,
+        }])];
+        let mut input = Input {
+            place: 4,
+            grade_and_board_history: &[],
+            battle_logs: None,
+            emo_bases: &emo_bases,
+        };
+        assert_eq!(evaluate_achievements(&input, &[]), Vec::<u16>::new());
+
+        input.battle_logs = Some(&logs);
+        assert_eq!(evaluate_achievements(&input, &[]), vec![9]);
+    }
+}
@@ -1,10 +1,51 @@
+use crate::codec_types::*;
+use sp_std::prelude::*;
+
 pub const INITIAL_EP: u16 = 300;
 pub const EP_BANDWIDTH: u16 = 100;
 pub const MAX_EP: u16 = u16::MAX;
 pub const MIN_EP: u16 = 1;
-pub const EP_K: f32 = 16f32;
 pub const EP_UNFINISH_PENALTY: u16 = 60;
 
+// the rating deviation (rd) is the uncertainty of the ep, a new player has the max rd
+pub const MAX_RD: u16 = 350;
+pub const MIN_RD: u16 = 30;
+// the fixed volatility, the rd increase for a rating period without a match
+pub const RD_INCREASE_PER_PERIOD: u16 = 35;
+pub const RATING_PERIOD_BLOCKS: u32 = 14_400;
+
+// fixed-point constants, q = ln(10) / 400 converts eps to the glicko-2 scale
+const Q_Q32: i64 = 24_723_819;
+const Q2_Q32: i64 = 142_322;
+// 3 * q^2 / pi^2
+const G_K_Q32: i64 = 43_261;
+const LOG2_10_OVER_400_Q32: i64 = 35_668_931;
+const ONE_Q16: i64 = 1 << 16;
+
+// 2^(-k/64) for k in 0..=64
+const EXP2_NEG_Q16: [i64; 65] = [
+    65536, 64830, 64132, 63441, 62757, 62081, 61413, 60751, 60097, 59449, 58809, 58176, 57549,
+    56929, 56316, 55709, 55109, 54515, 53928, 53347, 52773, 52204, 51642, 51085, 50535, 49991,
+    49452, 48920, 48393, 47871, 47356, 46846, 46341, 45842, 45348, 44859, 44376, 43898, 43425,
+    42958, 42495, 42037, 41584, 41136, 40693, 40255, 39821, 39392, 38968, 38548, 38133, 37722,
+    37316, 36914, 36516, 36123, 35734, 35349, 34968, 34591, 34219, 33850, 33486, 33125, 32768,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rating {
+    pub ep: u16,
+    pub rd: u16,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            ep: INITIAL_EP,
+            rd: MAX_RD,
+        }
+    }
+}
+
 pub fn get_ep_band(ep: u16) -> u16 {
     ep / EP_BANDWIDTH
 }
@@ -18,31 +59,114 @@ pub fn reduce_ep(ep: u16, minus: u16) -> u16 {
     }
 }
 
-#[cfg(feature = "chain")]
-pub fn calculate_new_ep(player_ep: u16, player_place: u8, sorted_ghost_eps: &[u16]) -> u16 {
-    let player_ep: f32 = player_ep.into();
-    let mut diff = 0f32;
+// the rd grows while the player doesn't play, so a returning player converges quickly
+pub fn increase_rd(rd: u16, periods: u32) -> u16 {
+    let rd2 = (rd as u64).pow(2)
+        + (RD_INCREASE_PER_PERIOD as u64).pow(2) * periods.min(MAX_RD as u32) as u64;
+
+    (isqrt(rd2) as u16).clamp(MIN_RD, MAX_RD)
+}
+
+// glicko-2 with a fixed volatility in integer arithmetic, the ratings are on the ep scale.
+// sorted_ghost_ratings are sorted by the ghost's final health descending,
+// the player wins against the ghosts from the index of player_place - 1.
+// a ghost without a rating, such as a bot, keeps its position but isn't rated against
+pub fn calculate_new_rating(
+    player: Rating,
+    player_place: u8,
+    sorted_ghost_ratings: &[Option<Rating>],
+    periods: u32,
+) -> Rating {
+    let pre_rd2 = (increase_rd(player.rd, periods) as i64).pow(2);
+
+    let mut variance_inv_q16 = 0i64;
+    let mut improvement_q16 = 0i64;
+
+    for (ghost, i) in sorted_ghost_ratings.iter().zip(0u8..) {
+        let ghost = match ghost {
+            Some(ghost) => ghost,
+            None => continue,
+        };
+        let g = get_g_q16(ghost.rd);
+        let e = get_expected_score_q16(g * (player.ep as i64 - ghost.ep as i64));
+        let s = if player_place <= i + 1 { ONE_Q16 } else { 0 };
 
-    for (&ep, i) in sorted_ghost_eps.iter().zip(0u8..) {
-        let s = if player_place - 1 <= i { 1f32 } else { 0f32 };
-        diff += EP_K * (s - calculate_expected(player_ep, ep.into()));
+        variance_inv_q16 += (((g * g) >> 16) * ((e * (ONE_Q16 - e)) >> 16)) >> 16;
+        improvement_q16 += (g * (s - e)) >> 16;
     }
 
-    let new = player_ep + diff;
+    // rd'^2 = 1 / (1 / rd^2 + q^2 * v^-1)
+    let denominator_q16 = ONE_Q16 + ((Q2_Q32 * variance_inv_q16 * pre_rd2) >> 32);
+    let rd2 = (pre_rd2 << 16) / denominator_q16;
 
-    if new >= MAX_EP as f32 {
-        return MAX_EP;
+    // ep' = ep + q * rd'^2 * sum(g * (s - e))
+    let delta = (Q_Q32 * rd2 * improvement_q16 + (1 << 47)) >> 48;
+    let ep = (player.ep as i64 + delta).clamp(MIN_EP as i64, MAX_EP as i64) as u16;
+    let rd = (isqrt(rd2 as u64) as u16).clamp(MIN_RD, MAX_RD);
+
+    Rating { ep, rd }
+}
+
+// sorts the ghost ratings by the ghost's final health descending
+pub fn sort_ghost_ratings(
+    ghost_states: &[mtc::GhostState],
+    ghost_ratings: &[Option<Rating>],
+) -> Vec<Option<Rating>> {
+    let mut ghosts = ghost_states
+        .iter()
+        .map(|s| {
+            if let mtc::GhostState::Active { health } = s {
+                *health
+            } else {
+                0
+            }
+        })
+        .zip(ghost_ratings.iter().copied())
+        .collect::<Vec<_>>();
+
+    ghosts.sort_by_key(|&(health, _)| health);
+    ghosts.reverse();
+
+    ghosts.into_iter().map(|(_, rating)| rating).collect()
+}
+
+// g(rd) = 1 / sqrt(1 + 3 * q^2 * rd^2 / pi^2)
+fn get_g_q16(rd: u16) -> i64 {
+    (1i64 << 32) / isqrt((1u64 << 32) + (rd as u64).pow(2) * G_K_Q32 as u64) as i64
+}
+
+// 1 / (1 + 10^(-diff / 400)) with 2^(-x) by the table and the linear interpolation
+fn get_expected_score_q16(diff_q16: i64) -> i64 {
+    if diff_q16 < 0 {
+        return ONE_Q16 - get_expected_score_q16(-diff_q16);
     }
-    if new <= MIN_EP as f32 {
-        return MIN_EP;
+
+    let exponent_q16 = ((diff_q16 as i128 * LOG2_10_OVER_400_Q32 as i128) >> 32) as i64;
+    let int = exponent_q16 >> 16;
+    if int >= 32 {
+        return ONE_Q16;
     }
 
-    libm::roundf(new) as u16
+    let frac = exponent_q16 & 0xffff;
+    let k = (frac >> 10) as usize;
+    let rem = frac & 0x3ff;
+    let p = (EXP2_NEG_Q16[k] - (((EXP2_NEG_Q16[k] - EXP2_NEG_Q16[k + 1]) * rem) >> 10)) >> int;
+
+    (1i64 << 32) / (ONE_Q16 + p)
 }
 
-#[cfg(feature = "chain")]
-fn calculate_expected(a: f32, b: f32) -> f32 {
-    1.0 / (1.0 + (libm::powf(10f32, (b - a) / 400.0)))
+fn isqrt(n: u64) -> u64 {
+    if n < 2 {
+        return n;
+    }
+
+    let mut x = n;
+    let mut y = (x + 1) / 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
 }
 
 #[cfg(test)]
@@ -56,4 +180,164 @@ mod tests {
         assert_eq!(reduce_ep(10, 10), 1);
         assert_eq!(reduce_ep(10, 11), 1);
     }
+
+    fn calculate_new_rating_float(
+        player: Rating,
+        player_place: u8,
+        sorted_ghost_ratings: &[Rating],
+        periods: u32,
+    ) -> (f64, f64) {
+        let q = 10f64.ln() / 400.0;
+        let g =
+            |rd: f64| 1.0 / (1.0 + 3.0 * q * q * rd * rd / core::f64::consts::PI.powi(2)).sqrt();
+        let pre_rd = increase_rd(player.rd, periods) as f64;
+
+        let mut variance_inv = 0.0;
+        let mut improvement = 0.0;
+        for (ghost, i) in sorted_ghost_ratings.iter().zip(0u8..) {
+            let g = g(ghost.rd as f64);
+            let e = 1.0 / (1.0 + 10f64.powf(-g * (player.ep as f64 - ghost.ep as f64) / 400.0));
+            let s = if player_place <= i + 1 { 1.0 } else { 0.0 };
+            variance_inv += g * g * e * (1.0 - e);
+            improvement += g * (s - e);
+        }
+
+        let rd2 = 1.0 / (1.0 / (pre_rd * pre_rd) + q * q * variance_inv);
+        (player.ep as f64 + q * rd2 * improvement, rd2.sqrt())
+    }
+
+    #[test]
+    fn test_sort_ghost_ratings() {
+        let ratings = [
+            Some(Rating { ep: 1, rd: 50 }),
+            None,
+            Some(Rating { ep: 3, rd: 50 }),
+        ];
+        assert_eq!(
+            sort_ghost_ratings(
+                &[
+                    mtc::GhostState::Active { health: 1 },
+                    mtc::GhostState::Retired { final_turn: 2 },
+                    mtc::GhostState::Active { health: 5 },
+                ],
+                &ratings
+            ),
+            vec![ratings[2], ratings[0], ratings[1]]
+        );
+    }
+
+    #[test]
+    fn test_increase_rd() {
+        assert_eq!(increase_rd(50, 0), 50);
+        assert_eq!(increase_rd(50, 1), 61);
+        assert_eq!(increase_rd(50, 10), 121);
+        assert_eq!(increase_rd(50, 1_000), MAX_RD);
+        assert_eq!(increase_rd(10, 0), MIN_RD);
+    }
+
+    #[test]
+    fn test_get_expected_score() {
+        for diff in (-2_000..=2_000).step_by(7) {
+            let e = get_expected_score_q16((diff as i64) << 16) as f64 / ONE_Q16 as f64;
+            let expected = 1.0 / (1.0 + 10f64.powf(-diff as f64 / 400.0));
+            assert!((e - expected).abs() < 1e-4, "diff: {}", diff);
+        }
+        assert_eq!(get_expected_score_q16(0), ONE_Q16 / 2);
+        assert_eq!(get_expected_score_q16(i64::from(MAX_EP) << 16), ONE_Q16);
+        assert_eq!(get_expected_score_q16(-(i64::from(MAX_EP) << 16)), 0);
+    }
+
+    #[test]
+    fn test_calculate_new_rating() {
+        let cases = [
+            (Rating::default(), [Rating::default(); 3], 0),
+            (
+                Rating { ep: 300, rd: 50 },
+                [
+                    Rating { ep: 400, rd: 50 },
+                    Rating { ep: 300, rd: 100 },
+                    Rating { ep: 200, rd: 350 },
+                ],
+                0,
+            ),
+            (
+                Rating { ep: 1_500, rd: 30 },
+                [
+                    Rating { ep: 1_200, rd: 30 },
+                    Rating { ep: 1_700, rd: 200 },
+                    Rating { ep: 1_500, rd: 80 },
+                ],
+                5,
+            ),
+            (
+                Rating { ep: 3_000, rd: 120 },
+                [
+                    Rating { ep: 100, rd: 30 },
+                    Rating { ep: 5_000, rd: 30 },
+                    Rating { ep: 3_000, rd: 350 },
+                ],
+                100,
+            ),
+        ];
+
+        for (player, ghosts, periods) in cases.iter() {
+            for place in 1..=4 {
+                let rating = calculate_new_rating(*player, place, &ghosts.map(Some), *periods);
+                let (ep, rd) = calculate_new_rating_float(*player, place, ghosts, *periods);
+
+                assert!(
+                    (rating.ep as f64 - ep).abs() <= 1.0,
+                    "{:?} {} {}",
+                    player,
+                    place,
+                    ep
+                );
+                assert!(
+                    (rating.rd as f64 - rd.max(MIN_RD as f64)).abs() <= 1.0,
+                    "{:?} {} {}",
+                    player,
+                    place,
+                    rd
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_calculate_new_rating_convergence() {
+        let ghosts = [Some(Rating { ep: 300, rd: 50 }); 3];
+
+        let new_player = calculate_new_rating(Rating::default(), 1, &ghosts, 0);
+        let established = calculate_new_rating(Rating { ep: 300, rd: 50 }, 1, &ghosts, 0);
+        assert!(new_player.ep > established.ep);
+        assert!(new_player.rd < MAX_RD);
+
+        let returning = calculate_new_rating(Rating { ep: 300, rd: 50 }, 1, &ghosts, 30);
+        assert!(returning.ep > established.ep);
+
+        assert!(calculate_new_rating(Rating::default(), 4, &ghosts, 0).ep < INITIAL_EP);
+        assert_eq!(
+            calculate_new_rating(Rating { ep: MIN_EP, rd: 50 }, 4, &ghosts, 0).ep,
+            MIN_EP
+        );
+        assert_eq!(
+            calculate_new_rating(Rating { ep: 300, rd: 50 }, 1, &[None; 3], 0),
+            Rating { ep: 300, rd: 50 }
+        );
+
+        // the position of an unrated ghost still counts for the place
+        let rated = Some(Rating { ep: 300, rd: 50 });
+        assert_eq!(
+            calculate_new_rating(Rating::default(), 2, &[None, rated, rated], 0),
+            calculate_new_rating(Rating::default(), 1, &[rated, rated], 0)
+        );
+        assert_eq!(
+            calculate_new_rating(Rating::default(), 3, &[rated, None, rated], 0),
+            calculate_new_rating(Rating::default(), 2, &[rated, rated], 0)
+        );
+        assert_eq!(
+            calculate_new_rating(Rating { ep: 300, rd: 50 }, 1, &[], 0),
+            Rating { ep: 300, rd: 50 }
+        );
+    }
 }
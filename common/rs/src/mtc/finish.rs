@@ -1,6 +1,8 @@
 use crate::codec_types::*;
 use sp_std::prelude::*;
 
+pub const GRADE_AND_BOARD_HISTORY_LIMIT: u8 = 30;
+
 pub fn get_turn_and_previous_grade_and_board(
     history: &[mtc::GradeAndBoard],
 ) -> (u8, mtc::GradeAndBoard) {
@@ -21,5 +23,5 @@ pub fn get_turn_and_previous_grade_and_board(
 pub fn exceeds_grade_and_board_history_limit(
     grade_and_board_history: &[mtc::GradeAndBoard],
 ) -> bool {
-    grade_and_board_history.len() > 30
+    grade_and_board_history.len() > GRADE_AND_BOARD_HISTORY_LIMIT as usize
 }
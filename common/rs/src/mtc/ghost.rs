@@ -1,7 +1,13 @@
-use crate::{codec_types::*, mtc::ep::get_ep_band};
+use crate::{
+    codec_types::*,
+    mtc::{
+        ep::{get_ep_band, Rating},
+        utils::{build_emo_attributes, BOARD_EMO_MAX_COUNT},
+    },
+};
 use rand::{
     seq::{IteratorRandom, SliceRandom},
-    SeedableRng,
+    Rng, SeedableRng,
 };
 use rand_pcg::Pcg64Mcg;
 use sp_std::prelude::*;
@@ -9,46 +15,83 @@ use sp_std::prelude::*;
 const GHOST_COUNT: u8 = 3;
 const GHOST_COUNT_USIZE: usize = GHOST_COUNT as usize;
 
+// the weight of a ghost registered in the current block
+const MAX_RECENCY_WEIGHT: u64 = 1 << 16;
+
+pub const MATCHMAKING_GHOSTS_PER_BAND: usize = 20;
+
+// the last board of a bot ghost is reused after this turn
+const BOT_GHOST_TURN_LEN: u8 = 12;
+const BOT_GHOST_MAX_GRADE: u8 = 6;
+
+// the matchmaking ghosts of an ep band are stored as the list of
+// (registered block number, account, registered ep) and the ghosts by (ep band, index in the list)
+pub trait MatchmakingStorage<A> {
+    fn get_ghosts_info(&self, ep_band: u16) -> Option<Vec<(u32, A, u16)>>;
+    fn insert_ghosts_info(&mut self, ep_band: u16, info: Vec<(u32, A, u16)>);
+    fn get_ghost(&self, ep_band: u16, index: u8) -> Option<mtc::Ghost>;
+    fn insert_ghost(&mut self, ep_band: u16, index: u8, ghost: mtc::Ghost);
+}
+
+pub struct ChooseGhostsOptions<'a, A> {
+    pub block_number: u32,
+    // the player and the recent opponents
+    pub excluded_accounts: &'a [A],
+    // bands above the player's band to search when the lower bands are not enough
+    pub upward_band_search_len: u16,
+    // the weight of a ghost halves every half life blocks, 0 disables the weighting
+    pub recency_half_life: u32,
+}
+
+// searches the player's band and the lower bands first, then the upper bands.
+// each chosen ghost is (account, registered ep, ghost)
 pub fn choose_ghosts<A, F0, F1>(
     ep: u16,
     seed: u64,
+    options: &ChooseGhostsOptions<A>,
     get_ghosts_info: &F0,
     get_ghost: &F1,
-) -> Vec<Option<(A, mtc::Ghost)>>
+) -> Vec<Option<(A, u16, mtc::Ghost)>>
 where
-    F0: Fn(u16) -> Option<Vec<A>>,
+    A: PartialEq,
+    F0: Fn(u16) -> Option<Vec<(u32, A, u16)>>,
     F1: Fn((u16, u8)) -> Option<mtc::Ghost>,
 {
     let mut rng = Pcg64Mcg::seed_from_u64(seed);
 
-    let mut ep_band = get_ep_band(ep);
-    let mut ghosts_infos = Vec::new();
+    let ep_band = get_ep_band(ep);
+    let upper_ep_band = ep_band.saturating_add(options.upward_band_search_len);
+    let mut candidates_by_band = Vec::new();
+    let mut candidates_len = 0;
 
-    loop {
-        if let Some(v) = get_ghosts_info(ep_band) {
-            ghosts_infos.push((ep_band, v));
+    for band in (0..=ep_band).rev().chain((ep_band + 1)..=upper_ep_band) {
+        if let Some(v) = get_ghosts_info(band) {
+            let candidates = v
+                .into_iter()
+                .zip(0u8..)
+                .filter(|((_, a, _), _)| !options.excluded_accounts.contains(a))
+                .map(|((block_number, a, ep), index)| {
+                    (get_recency_weight(block_number, options), (a, ep), index)
+                })
+                .collect::<Vec<_>>();
 
-            if ghosts_infos.iter().map(|(_, v)| v.len()).sum::<usize>() >= GHOST_COUNT_USIZE {
+            candidates_len += candidates.len();
+            candidates_by_band.push((band, candidates));
+
+            if candidates_len >= GHOST_COUNT_USIZE {
                 break;
             }
         }
-
-        if ep_band < 1 {
-            break;
-        }
-
-        ep_band -= 1;
     }
 
     let mut choosen_ghosts = Vec::with_capacity(GHOST_COUNT_USIZE);
 
-    for (band, v) in ghosts_infos.into_iter() {
+    for (band, candidates) in candidates_by_band.into_iter() {
+        let amount = GHOST_COUNT_USIZE - choosen_ghosts.len();
         choosen_ghosts.extend(
-            v.into_iter()
-                .zip(0u8..)
-                .choose_multiple(&mut rng, GHOST_COUNT_USIZE - choosen_ghosts.len())
+            choose_weighted(&mut rng, candidates, amount)
                 .into_iter()
-                .map(|(a, index)| Some((a, get_ghost((band, index)).unwrap()))),
+                .map(|((a, ep), index)| Some((a, ep, get_ghost((band, index)).unwrap()))),
         );
     }
 
@@ -61,51 +104,264 @@ where
     choosen_ghosts
 }
 
-pub fn separate_player_ghosts<T>(
-    player_ghosts: Vec<(T, u16, mtc::Ghost)>,
-) -> (Vec<mtc::Ghost>, Vec<u16>) {
-    let len = player_ghosts.len();
+pub fn choose_matchmaking_ghosts<A, S>(
+    storage: &S,
+    ep: u16,
+    seed: u64,
+    options: &ChooseGhostsOptions<A>,
+) -> Vec<Option<(A, u16, mtc::Ghost)>>
+where
+    A: PartialEq,
+    S: MatchmakingStorage<A>,
+{
+    choose_ghosts(
+        ep,
+        seed,
+        options,
+        &|ep_band| storage.get_ghosts_info(ep_band),
+        &|(ep_band, index)| storage.get_ghost(ep_band, index),
+    )
+}
+
+fn get_recency_weight<A>(block_number: u32, options: &ChooseGhostsOptions<A>) -> u64 {
+    if options.recency_half_life == 0 {
+        return 1;
+    }
+
+    let halvings = options.block_number.saturating_sub(block_number) / options.recency_half_life;
+
+    (MAX_RECENCY_WEIGHT >> halvings.min(16)).max(1)
+}
+
+// weighted sampling without replacement
+fn choose_weighted<A, R: Rng>(
+    rng: &mut R,
+    mut candidates: Vec<(u64, A, u8)>,
+    amount: usize,
+) -> Vec<(A, u8)> {
+    let mut choosen = Vec::with_capacity(amount);
 
-    let mut ghosts = Vec::with_capacity(len);
-    let mut ghost_eps = Vec::with_capacity(len);
+    while choosen.len() < amount && !candidates.is_empty() {
+        let total = candidates.iter().map(|&(w, _, _)| w).sum::<u64>();
+        let mut point = rng.gen_range(0..total);
 
-    for (_, ep, ghost) in player_ghosts.into_iter() {
-        ghosts.push(ghost);
-        ghost_eps.push(ep);
+        let i = candidates
+            .iter()
+            .position(|&(w, _, _)| {
+                if point < w {
+                    true
+                } else {
+                    point -= w;
+                    false
+                }
+            })
+            .unwrap();
+
+        let (_, a, index) = candidates.swap_remove(i);
+        choosen.push((a, index));
     }
 
-    (ghosts, ghost_eps)
+    choosen
+}
+
+// replaces the missing ghosts with bot ghosts, which have no account
+pub fn fill_with_bot_ghosts<A>(
+    ghosts: Vec<Option<(A, mtc::Ghost)>>,
+    seed: u64,
+    emo_bases: &emo::Bases,
+    deck_base_ids: &[u16],
+) -> Vec<(Option<A>, mtc::Ghost)> {
+    let mut rng = Pcg64Mcg::seed_from_u64(seed);
+
+    ghosts
+        .into_iter()
+        .map(|o| match o {
+            Some((a, ghost)) => (Some(a), ghost),
+            None => (None, build_bot_ghost(rng.gen(), emo_bases, deck_base_ids)),
+        })
+        .collect()
 }
 
-pub fn build_matchmaking_ghosts<A, F>(
-    account_id: &A,
+// the ghosts of an mtc with the eps they are rated by.
+// a ghost keeps the ep it was registered with, and a bot ghost shows the player's ep
+pub fn matchmake<A, S>(
+    storage: &S,
     ep: u16,
-    grade_and_board_history: &[mtc::GradeAndBoard],
-    get_matchmaking_ghosts: &F,
-) -> (u16, Vec<(A, u16, mtc::Ghost)>)
+    seed: u64,
+    options: &ChooseGhostsOptions<A>,
+    emo_bases: &emo::Bases,
+    deck_base_ids: &[u16],
+) -> Vec<(Option<A>, u16, mtc::Ghost)>
 where
-    A: Eq + Clone,
-    F: Fn(u16) -> Option<Vec<(A, u16, mtc::Ghost)>>,
+    A: PartialEq,
+    S: MatchmakingStorage<A>,
 {
-    let ep_band = get_ep_band(ep);
-    let ghost = build_ghost_from_history(grade_and_board_history);
+    fill_with_bot_ghosts(
+        choose_matchmaking_ghosts(storage, ep, seed, options)
+            .into_iter()
+            .map(|g| g.map(|(a, ghost_ep, ghost)| ((a, ghost_ep), ghost)))
+            .collect(),
+        seed,
+        emo_bases,
+        deck_base_ids,
+    )
+    .into_iter()
+    .map(|(a, ghost)| match a {
+        Some((a, ghost_ep)) => (Some(a), ghost_ep, ghost),
+        None => (None, ep, ghost),
+    })
+    .collect()
+}
 
-    let mut ghosts_with_data = get_matchmaking_ghosts(ep_band).unwrap_or_default();
+// a bot ghost isn't rated, it would only be an ep farm
+pub fn get_ghost_ratings<A, F>(ghosts: &[(Option<A>, u16)], get_rd: F) -> Vec<Option<Rating>>
+where
+    F: Fn(&A) -> u16,
+{
+    ghosts
+        .iter()
+        .map(|(a, ep)| {
+            a.as_ref().map(|a| Rating {
+                ep: *ep,
+                rd: get_rd(a),
+            })
+        })
+        .collect()
+}
 
-    if let Some(ghost_with_data) = ghosts_with_data
-        .iter_mut()
-        .find(|(aid, _, _)| aid == account_id)
+// the ghost of a finished mtc is registered with the new ep,
+// unless the player finished last or the final board is empty
+pub fn register_finished_mtc_ghost<A, S>(
+    storage: &mut S,
+    account: A,
+    place: u8,
+    ep: u16,
+    block_number: u32,
+    grade_and_board_history: &[mtc::GradeAndBoard],
+) where
+    A: PartialEq,
+    S: MatchmakingStorage<A>,
+{
+    if place > GHOST_COUNT
+        || grade_and_board_history
+            .last()
+            .map_or(true, |h| h.board.0.is_empty())
     {
-        ghost_with_data.1 = ep;
-        ghost_with_data.2 = ghost;
-    } else if ghosts_with_data.len() < 20 {
-        ghosts_with_data.push((account_id.clone(), ep, ghost));
-    } else {
-        ghosts_with_data.remove(0);
-        ghosts_with_data.push((account_id.clone(), ep, ghost));
+        return;
     }
 
-    (ep_band, ghosts_with_data)
+    register_matchmaking_ghost(storage, account, ep, block_number, grade_and_board_history);
+}
+
+// the opponents of the last mtcs, oldest first. at most 3 per mtc
+pub fn update_recent_opponents<A: Clone>(
+    mut recent_opponents: Vec<A>,
+    opponents: &[Option<A>],
+    mtc_len: u8,
+) -> Vec<A> {
+    recent_opponents.extend(opponents.iter().flatten().cloned());
+
+    let max_len = mtc_len as usize * GHOST_COUNT_USIZE;
+    if recent_opponents.len() > max_len {
+        recent_opponents.drain(..recent_opponents.len() - max_len);
+    }
+
+    recent_opponents
+}
+
+// a board grows by one emo of the deck bases per turn,
+// and the lowest grade emo is replaced when the board is full
+pub fn build_bot_ghost(seed: u64, emo_bases: &emo::Bases, deck_base_ids: &[u16]) -> mtc::Ghost {
+    let mut rng = Pcg64Mcg::seed_from_u64(seed);
+    let mut board: Vec<mtc::GhostBoardEmo> = Vec::new();
+    let mut history = Vec::with_capacity(BOT_GHOST_TURN_LEN as usize);
+
+    for turn in 1..=BOT_GHOST_TURN_LEN {
+        let grade = get_bot_grade(turn);
+
+        let base = deck_base_ids
+            .iter()
+            .filter_map(|&id| emo_bases.find(id).ok())
+            .filter(|b| b.grade >= 1 && b.grade <= grade)
+            .choose(&mut rng);
+
+        if let Some(base) = base {
+            let emo = mtc::GhostBoardEmo {
+                base_id: base.id,
+                attributes: build_emo_attributes(base, false),
+            };
+
+            if board.len() < BOARD_EMO_MAX_COUNT as usize {
+                let index = rng.gen_range(0..=board.len());
+                board.insert(index, emo);
+            } else if let Some(index) = get_lowest_grade_emo_index(&board, emo_bases) {
+                board[index] = emo;
+            }
+        }
+
+        history.push(mtc::GradeAndGhostBoard {
+            grade,
+            board: mtc::GhostBoard(board.clone()),
+        });
+    }
+
+    mtc::Ghost { history }
+}
+
+// upgrades every other turn
+fn get_bot_grade(turn: u8) -> u8 {
+    (1 + turn / 2).min(BOT_GHOST_MAX_GRADE)
+}
+
+fn get_lowest_grade_emo_index(
+    board: &[mtc::GhostBoardEmo],
+    emo_bases: &emo::Bases,
+) -> Option<usize> {
+    board
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, e)| emo_bases.find(e.base_id).map_or(0, |b| b.grade))
+        .map(|(i, _)| i)
+}
+
+// replaces the player's ghost in the band, or the oldest ghost if the band is full
+pub fn register_matchmaking_ghost<A, S>(
+    storage: &mut S,
+    account: A,
+    ep: u16,
+    block_number: u32,
+    grade_and_board_history: &[mtc::GradeAndBoard],
+) where
+    A: PartialEq,
+    S: MatchmakingStorage<A>,
+{
+    let ep_band = get_ep_band(ep);
+    let mut info = storage.get_ghosts_info(ep_band).unwrap_or_default();
+
+    let index = if let Some(index) = info.iter().position(|(_, a, _)| a == &account) {
+        info[index] = (block_number, account, ep);
+        index
+    } else if info.len() < MATCHMAKING_GHOSTS_PER_BAND {
+        info.push((block_number, account, ep));
+        info.len() - 1
+    } else {
+        // the first one wins a tie
+        let index = info
+            .iter()
+            .enumerate()
+            .min_by_key(|&(i, &(b, _, _))| (b, i))
+            .map(|(i, _)| i)
+            .unwrap();
+        info[index] = (block_number, account, ep);
+        index
+    };
+
+    storage.insert_ghosts_info(ep_band, info);
+    storage.insert_ghost(
+        ep_band,
+        index as u8,
+        build_ghost_from_history(grade_and_board_history),
+    );
 }
 
 pub fn build_ghost_from_history(grade_and_board_history: &[mtc::GradeAndBoard]) -> mtc::Ghost {
@@ -135,30 +391,68 @@ fn build_ghost_board_from_board(board: &mtc::Board) -> mtc::GhostBoard {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sp_std::collections::btree_map::BTreeMap;
+
+    type A = u8;
+
+    const OPTIONS: ChooseGhostsOptions<A> = ChooseGhostsOptions {
+        block_number: 0,
+        excluded_accounts: &[],
+        upward_band_search_len: 0,
+        recency_half_life: 0,
+    };
+
+    fn create_ghost(grade: u8) -> mtc::Ghost {
+        mtc::Ghost {
+            history: vec![mtc::GradeAndGhostBoard {
+                grade,
+                board: mtc::GhostBoard(vec![]),
+            }],
+        }
+    }
+
+    // one base per grade, the id is the grade
+    fn build_emo_bases() -> emo::Bases {
+        let mut bases = emo::Bases::new();
+        for grade in 1..=BOT_GHOST_MAX_GRADE {
+            bases.add(emo::Base {
+                id: grade as u16,
+                grade,
+                attack: 10,
+                health: 10,
+                ..Default::default()
+            });
+        }
+        bases
+    }
+
+    fn build_deck_base_ids() -> Vec<u16> {
+        (1..=BOT_GHOST_MAX_GRADE as u16).collect()
+    }
+
+    // the registered ep is 300 + account
+    fn build_info(accounts: Vec<A>) -> Option<Vec<(u32, A, u16)>> {
+        Some(
+            accounts
+                .into_iter()
+                .map(|a| (0, a, 300 + a as u16))
+                .collect(),
+        )
+    }
 
     #[test]
     fn test_choose_ghosts() {
         let seed = 0;
-        type A = u8;
-        let a_vec_none: Option<Vec<A>> = None;
+        let a_vec_none: Option<Vec<(u32, A, u16)>> = None;
         let panic_closure = |_| panic!("should not reached");
 
-        fn create_ghost(grade: u8) -> mtc::Ghost {
-            mtc::Ghost {
-                history: vec![mtc::GradeAndGhostBoard {
-                    grade,
-                    board: mtc::GhostBoard(vec![]),
-                }],
-            }
-        }
-
         assert_eq!(
-            choose_ghosts(300, seed, &|_| a_vec_none.clone(), &panic_closure),
+            choose_ghosts(300, seed, &OPTIONS, &|_| a_vec_none.clone(), &panic_closure),
             vec![None, None, None]
         );
 
         assert_eq!(
-            choose_ghosts(1, seed, &|_| a_vec_none.clone(), &panic_closure),
+            choose_ghosts(1, seed, &OPTIONS, &|_| a_vec_none.clone(), &panic_closure),
             vec![None, None, None]
         );
 
@@ -166,8 +460,9 @@ mod tests {
             choose_ghosts(
                 300,
                 seed,
+                &OPTIONS,
                 &|b| if b == 3 {
-                    Some(vec![1])
+                    build_info(vec![1])
                 } else {
                     a_vec_none.clone()
                 },
@@ -177,17 +472,18 @@ mod tests {
                     panic!("should not reached")
                 }
             ),
-            vec![Some((1, create_ghost(1))), None, None]
+            vec![Some((1, 301, create_ghost(1))), None, None]
         );
 
         assert_eq!(
             choose_ghosts(
                 300,
                 seed,
+                &OPTIONS,
                 &|b| match b {
-                    2 => Some(vec![3]),
-                    1 => Some(vec![2]),
-                    0 => Some(vec![1]),
+                    2 => build_info(vec![3]),
+                    1 => build_info(vec![2]),
+                    0 => build_info(vec![1]),
                     _ => a_vec_none.clone(),
                 },
                 &|(b, i)| {
@@ -201,9 +497,9 @@ mod tests {
                 }
             ),
             vec![
-                Some((2, create_ghost(2))),
-                Some((3, create_ghost(3))),
-                Some((1, create_ghost(1))),
+                Some((2, 302, create_ghost(2))),
+                Some((1, 301, create_ghost(1))),
+                Some((3, 303, create_ghost(3))),
             ]
         );
 
@@ -211,8 +507,9 @@ mod tests {
             choose_ghosts(
                 300,
                 seed,
+                &OPTIONS,
                 &|b| if b == 3 {
-                    Some(vec![1, 2, 3])
+                    build_info(vec![1, 2, 3])
                 } else {
                     panic!("should not reached")
                 },
@@ -226,9 +523,9 @@ mod tests {
                 }
             ),
             vec![
-                Some((2, create_ghost(2))),
-                Some((1, create_ghost(1))),
-                Some((3, create_ghost(3))),
+                Some((3, 303, create_ghost(3))),
+                Some((1, 301, create_ghost(1))),
+                Some((2, 302, create_ghost(2))),
             ]
         );
 
@@ -236,8 +533,9 @@ mod tests {
             choose_ghosts(
                 300,
                 seed,
+                &OPTIONS,
                 &|b| if b == 3 {
-                    Some((1..=20).collect())
+                    build_info((1..=20).collect())
                 } else {
                     panic!("should not reached")
                 },
@@ -249,9 +547,338 @@ mod tests {
                 }
             ),
             vec![
-                Some((8, create_ghost(8))),
-                Some((4, create_ghost(4))),
-                Some((9, create_ghost(9))),
+                Some((8, 308, create_ghost(8))),
+                Some((20, 320, create_ghost(20))),
+                Some((1, 301, create_ghost(1))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_choose_ghosts_excluded_accounts() {
+        let options = ChooseGhostsOptions {
+            excluded_accounts: &[1, 3],
+            ..OPTIONS
+        };
+
+        let mut ghosts = choose_ghosts(
+            300,
+            0,
+            &options,
+            &|b| {
+                if b == 3 {
+                    build_info(vec![1, 2, 3, 4])
+                } else {
+                    None
+                }
+            },
+            &|(_, i)| Some(create_ghost(i + 1)),
+        );
+        ghosts.sort();
+
+        assert_eq!(
+            ghosts,
+            vec![
+                None,
+                Some((2, 302, create_ghost(2))),
+                Some((4, 304, create_ghost(4)))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_choose_ghosts_upward_band_search() {
+        let get_ghosts_info = |b| match b {
+            3 => build_info(vec![1]),
+            5 => build_info(vec![2, 3]),
+            _ => None,
+        };
+        let get_ghost = |(b, i)| Some(create_ghost(b as u8 * 10 + i));
+
+        let mut ghosts = choose_ghosts(300, 0, &OPTIONS, &get_ghosts_info, &get_ghost);
+        ghosts.sort();
+        assert_eq!(ghosts, vec![None, None, Some((1, 301, create_ghost(30)))]);
+
+        let options = ChooseGhostsOptions {
+            upward_band_search_len: 2,
+            ..OPTIONS
+        };
+        let mut ghosts = choose_ghosts(300, 0, &options, &get_ghosts_info, &get_ghost);
+        ghosts.sort();
+        assert_eq!(
+            ghosts,
+            vec![
+                Some((1, 301, create_ghost(30))),
+                Some((2, 302, create_ghost(50))),
+                Some((3, 303, create_ghost(51))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_choose_ghosts_recency_weight() {
+        let options = ChooseGhostsOptions {
+            block_number: 10_000,
+            recency_half_life: 100,
+            ..OPTIONS
+        };
+        let get_ghosts_info = |_| {
+            Some(vec![
+                (0, 1, 301),
+                (10_000, 2, 302),
+                (9_950, 3, 303),
+                (9_900, 4, 304),
+            ])
+        };
+
+        for seed in 0..100 {
+            let mut ghosts = choose_ghosts(300, seed, &options, &get_ghosts_info, &|(_, i)| {
+                Some(create_ghost(i + 1))
+            });
+            ghosts.sort();
+            assert_eq!(
+                ghosts,
+                vec![
+                    Some((2, 302, create_ghost(2))),
+                    Some((3, 303, create_ghost(3))),
+                    Some((4, 304, create_ghost(4))),
+                ],
+                "seed: {}",
+                seed
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_recency_weight() {
+        let options = ChooseGhostsOptions {
+            block_number: 1_000,
+            recency_half_life: 100,
+            ..OPTIONS
+        };
+        assert_eq!(get_recency_weight(1_000, &options), MAX_RECENCY_WEIGHT);
+        assert_eq!(get_recency_weight(901, &options), MAX_RECENCY_WEIGHT);
+        assert_eq!(get_recency_weight(900, &options), MAX_RECENCY_WEIGHT / 2);
+        assert_eq!(get_recency_weight(0, &options), MAX_RECENCY_WEIGHT >> 10);
+        assert_eq!(get_recency_weight(2_000, &options), MAX_RECENCY_WEIGHT);
+        assert_eq!(get_recency_weight(0, &OPTIONS), 1);
+    }
+
+    #[test]
+    fn test_fill_with_bot_ghosts() {
+        let emo_bases = build_emo_bases();
+        let built = build_deck_base_ids();
+
+        let ghosts = fill_with_bot_ghosts(
+            vec![None, Some((1, create_ghost(1))), None],
+            0,
+            &emo_bases,
+            &built,
+        );
+        assert_eq!(ghosts[0].0, None);
+        assert_eq!(ghosts[1], (Some(1), create_ghost(1)));
+        assert_eq!(ghosts[2].0, None);
+        assert_ne!(ghosts[0].1, ghosts[2].1);
+
+        assert_eq!(
+            fill_with_bot_ghosts::<A>(vec![None, None, None], 0, &emo_bases, &built),
+            fill_with_bot_ghosts::<A>(vec![None, None, None], 0, &emo_bases, &built),
+        );
+    }
+
+    #[test]
+    fn test_update_recent_opponents() {
+        assert_eq!(
+            update_recent_opponents(vec![], &[Some(1), None, Some(2)], 2),
+            vec![1, 2]
+        );
+        assert_eq!(
+            update_recent_opponents(vec![1, 2, 3, 4, 5], &[Some(6), Some(7), None], 2),
+            vec![2, 3, 4, 5, 6, 7]
+        );
+        assert_eq!(
+            update_recent_opponents(vec![1, 2], &[Some(3), Some(4), Some(5)], 1),
+            vec![3, 4, 5]
+        );
+        assert_eq!(
+            update_recent_opponents(vec![1, 2], &[Some(3)], 0),
+            Vec::<u8>::new()
+        );
+    }
+
+    #[test]
+    fn test_build_bot_ghost() {
+        let emo_bases = build_emo_bases();
+        let built = build_deck_base_ids();
+
+        for seed in 0..10 {
+            let ghost = build_bot_ghost(seed, &emo_bases, &built);
+            assert_eq!(ghost.history.len(), BOT_GHOST_TURN_LEN as usize);
+
+            for (h, turn) in ghost.history.iter().zip(1u8..) {
+                assert_eq!(h.grade, get_bot_grade(turn));
+                assert_eq!(h.board.0.len(), turn.min(BOARD_EMO_MAX_COUNT) as usize);
+                assert!(h
+                    .board
+                    .0
+                    .iter()
+                    .all(|e| emo_bases.find(e.base_id).unwrap().grade <= h.grade
+                        && built.contains(&e.base_id)));
+            }
+        }
+
+        assert_eq!(
+            build_bot_ghost(0, &emo_bases, &built).history[0]
+                .board
+                .0
+                .len(),
+            1
+        );
+        assert_eq!(
+            build_bot_ghost(0, &emo_bases, &[]).history[0].board.0.len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_get_bot_grade() {
+        assert_eq!(get_bot_grade(1), 1);
+        assert_eq!(get_bot_grade(2), 2);
+        assert_eq!(get_bot_grade(4), 3);
+        assert_eq!(get_bot_grade(10), 6);
+        assert_eq!(get_bot_grade(BOT_GHOST_TURN_LEN), 6);
+    }
+
+    #[derive(Default)]
+    struct TestStorage {
+        info: BTreeMap<u16, Vec<(u32, A, u16)>>,
+        ghosts: BTreeMap<(u16, u8), mtc::Ghost>,
+    }
+
+    impl MatchmakingStorage<A> for TestStorage {
+        fn get_ghosts_info(&self, ep_band: u16) -> Option<Vec<(u32, A, u16)>> {
+            self.info.get(&ep_band).cloned()
+        }
+
+        fn insert_ghosts_info(&mut self, ep_band: u16, info: Vec<(u32, A, u16)>) {
+            self.info.insert(ep_band, info);
+        }
+
+        fn get_ghost(&self, ep_band: u16, index: u8) -> Option<mtc::Ghost> {
+            self.ghosts.get(&(ep_band, index)).cloned()
+        }
+
+        fn insert_ghost(&mut self, ep_band: u16, index: u8, ghost: mtc::Ghost) {
+            self.ghosts.insert((ep_band, index), ghost);
+        }
+    }
+
+    fn build_history(grade: u8) -> Vec<mtc::GradeAndBoard> {
+        vec![mtc::GradeAndBoard {
+            grade,
+            board: mtc::Board(vec![]),
+        }]
+    }
+
+    #[test]
+    fn test_register_matchmaking_ghost() {
+        let mut storage = TestStorage::default();
+
+        for a in 0..(MATCHMAKING_GHOSTS_PER_BAND as A) {
+            register_matchmaking_ghost(&mut storage, a, 300, 100 - a as u32, &build_history(1));
+        }
+        assert_eq!(storage.info[&3].len(), MATCHMAKING_GHOSTS_PER_BAND);
+        assert_eq!(storage.info[&3][5], (95, 5, 300));
+
+        // the same account replaces its ghost
+        register_matchmaking_ghost(&mut storage, 5, 350, 200, &build_history(2));
+        assert_eq!(storage.info[&3][5], (200, 5, 350));
+        assert_eq!(storage.ghosts[&(3, 5)], create_ghost(2));
+
+        // the oldest one is evicted in a full band
+        register_matchmaking_ghost(&mut storage, 100, 300, 201, &build_history(3));
+        assert_eq!(storage.info[&3].len(), MATCHMAKING_GHOSTS_PER_BAND);
+        assert_eq!(storage.info[&3][19], (201, 100, 300));
+        assert_eq!(storage.ghosts[&(3, 19)], create_ghost(3));
+
+        register_matchmaking_ghost(&mut storage, 101, 0, 1, &build_history(1));
+        assert_eq!(storage.info[&0], vec![(1, 101, 0)]);
+        assert_eq!(storage.ghosts[&(0, 0)], create_ghost(1));
+    }
+
+    #[test]
+    fn test_register_finished_mtc_ghost() {
+        let mut storage = TestStorage::default();
+        let history = vec![mtc::GradeAndBoard {
+            grade: 2,
+            board: mtc::Board(vec![Default::default()]),
+        }];
+
+        register_finished_mtc_ghost(&mut storage, 1, 4, 300, 0, &history);
+        register_finished_mtc_ghost(&mut storage, 2, 1, 300, 0, &build_history(2));
+        register_finished_mtc_ghost(&mut storage, 3, 1, 300, 0, &[]);
+        assert_eq!(storage.info.get(&3), None);
+
+        register_finished_mtc_ghost(&mut storage, 4, 3, 310, 5, &history);
+        assert_eq!(storage.info[&3], vec![(5, 4, 310)]);
+        assert_eq!(storage.ghosts[&(3, 0)], build_ghost_from_history(&history));
+    }
+
+    #[test]
+    fn test_get_ghost_ratings() {
+        assert_eq!(
+            get_ghost_ratings(&[(Some(1), 310), (None, 300), (Some(2), 290)], |&a| {
+                a as u16 * 100
+            }),
+            vec![
+                Some(Rating { ep: 310, rd: 100 }),
+                None,
+                Some(Rating { ep: 290, rd: 200 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_matchmake() {
+        let mut storage = TestStorage::default();
+        let emo_bases = build_emo_bases();
+        let built = build_deck_base_ids();
+
+        register_matchmaking_ghost(&mut storage, 1, 320, 0, &build_history(1));
+
+        let ghosts = matchmake(&storage, 300, 0, &OPTIONS, &emo_bases, &built);
+        assert_eq!(ghosts.len(), 3);
+        assert!(ghosts.contains(&(Some(1), 320, create_ghost(1))));
+        assert_eq!(
+            ghosts
+                .iter()
+                .filter(|(a, ep, _)| a.is_none() && *ep == 300)
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_choose_matchmaking_ghosts() {
+        let mut storage = TestStorage::default();
+
+        assert_eq!(
+            choose_matchmaking_ghosts(&storage, 300, 0, &OPTIONS),
+            vec![None, None, None]
+        );
+
+        for a in 1..=3 {
+            register_matchmaking_ghost(&mut storage, a, 300, 0, &build_history(a));
+        }
+
+        let mut ghosts = choose_matchmaking_ghosts(&storage, 300, 0, &OPTIONS);
+        ghosts.sort();
+        assert_eq!(
+            ghosts,
+            vec![
+                Some((1, 300, create_ghost(1))),
+                Some((2, 300, create_ghost(2))),
+                Some((3, 300, create_ghost(3))),
             ]
         );
     }
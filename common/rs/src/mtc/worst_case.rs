@@ -0,0 +1,229 @@
+// worst case inputs of finish_mtc_shop for benchmarks
+use crate::{
+    codec_types::*,
+    mtc::{finish::GRADE_AND_BOARD_HISTORY_LIMIT, utils::BOARD_EMO_MAX_COUNT},
+};
+use sp_std::{collections::btree_map::BTreeMap, prelude::*};
+
+pub const PLAYER_OPERATIONS_LEN: usize = 199;
+pub const NESTED_ABILITY_DEPTH: u8 = 4;
+
+const GRADES: u8 = 6;
+const BASES_PER_GRADE: u16 = 4;
+
+pub fn build_emo_bases() -> emo::Bases {
+    let mut bases = BTreeMap::new();
+
+    for grade in 1..=GRADES {
+        for i in 0..BASES_PER_GRADE {
+            let id = get_base_id(grade, i);
+            bases.insert(
+                id,
+                emo::Base {
+                    id,
+                    typ: build_typ(i),
+                    codepoint: 128000 + id as u32,
+                    grade,
+                    attack: 10 * grade as u16,
+                    health: 30 * grade as u16,
+                    abilities: build_abilities(),
+                },
+            );
+        }
+    }
+
+    emo::Bases(bases)
+}
+
+// one base per grade is selectable, the others are fixed
+pub fn build_deck_base_ids() -> (Vec<u16>, Vec<u16>) {
+    let mut built = Vec::new();
+    let mut fixed = Vec::new();
+
+    for grade in 1..=GRADES {
+        built.push(get_base_id(grade, 0));
+        fixed.extend((1..BASES_PER_GRADE).map(|i| get_base_id(grade, i)));
+    }
+
+    (built, fixed)
+}
+
+pub fn build_board(emo_bases: &emo::Bases) -> mtc::Board {
+    mtc::Board(
+        (0..BOARD_EMO_MAX_COUNT as u16)
+            .map(|i| {
+                let base = emo_bases
+                    .find(get_base_id(GRADES, i % BASES_PER_GRADE))
+                    .expect("worst case base");
+                mtc::BoardEmo {
+                    mtc_emo_ids: vec![i * 3 + 1001, i * 3 + 1002, i * 3 + 1003],
+                    base_id: base.id,
+                    attributes: build_triple_attributes(base),
+                }
+            })
+            .collect(),
+    )
+}
+
+pub fn build_grade_and_board_history(emo_bases: &emo::Bases) -> Vec<mtc::GradeAndBoard> {
+    let board = build_board(emo_bases);
+
+    (0..GRADE_AND_BOARD_HISTORY_LIMIT)
+        .map(|_| mtc::GradeAndBoard {
+            grade: GRADES,
+            board: board.clone(),
+        })
+        .collect()
+}
+
+pub fn build_ghost(emo_bases: &emo::Bases) -> mtc::Ghost {
+    let board = mtc::GhostBoard(
+        build_board(emo_bases)
+            .0
+            .into_iter()
+            .map(|e| mtc::GhostBoardEmo {
+                base_id: e.base_id,
+                attributes: e.attributes,
+            })
+            .collect(),
+    );
+
+    mtc::Ghost {
+        history: (0..=GRADE_AND_BOARD_HISTORY_LIMIT)
+            .map(|_| mtc::GradeAndGhostBoard {
+                grade: GRADES,
+                board: board.clone(),
+            })
+            .collect(),
+    }
+}
+
+// reverses the full board on each operation
+pub fn build_player_operations() -> Vec<mtc::shop::PlayerOperation> {
+    let mut indexes = (0..BOARD_EMO_MAX_COUNT).collect::<Vec<_>>();
+
+    (0..PLAYER_OPERATIONS_LEN)
+        .map(|_| {
+            indexes.reverse();
+            mtc::shop::PlayerOperation::Move {
+                indexes: indexes.clone(),
+            }
+        })
+        .collect()
+}
+
+fn get_base_id(grade: u8, i: u16) -> u16 {
+    grade as u16 * 100 + i
+}
+
+fn build_typ(i: u16) -> emo::Typ {
+    match i % 4 {
+        0 => emo::Typ::Human,
+        1 => emo::Typ::Nature,
+        2 => emo::Typ::Food,
+        _ => emo::Typ::Object,
+    }
+}
+
+fn build_triple_attributes(base: &emo::Base) -> emo::Attributes {
+    emo::Attributes {
+        attack: base.attack * 2,
+        health: base.health * 2,
+        abilities: base.abilities.clone(),
+        is_triple: true,
+    }
+}
+
+fn build_abilities() -> Vec<emo::ability::Ability> {
+    vec![
+        emo::ability::Ability::Battle(emo::ability::battle::Battle::General(
+            emo::ability::battle::General::AsOneself {
+                trigger: emo::ability::battle::GeneralAsOneselfTrigger::Pre,
+                action: build_add_battle_ability_action(NESTED_ABILITY_DEPTH),
+            },
+        )),
+        emo::ability::Ability::Battle(build_nested_ability(NESTED_ABILITY_DEPTH)),
+    ]
+}
+
+// each retirement spreads the ability one level shallower to all allies
+fn build_nested_ability(depth: u8) -> emo::ability::battle::Battle {
+    let action = if depth == 0 {
+        emo::ability::battle::NormalAction::IncreaseStats {
+            target_or_random: build_all_allies(),
+            attack: 10,
+            health: 10,
+        }
+    } else {
+        build_add_battle_ability_action(depth - 1)
+    };
+
+    emo::ability::battle::Battle::General(emo::ability::battle::General::AsOneself {
+        trigger: emo::ability::battle::GeneralAsOneselfTrigger::Retire,
+        action,
+    })
+}
+
+fn build_add_battle_ability_action(depth: u8) -> emo::ability::battle::NormalAction {
+    emo::ability::battle::NormalAction::AddBattleAbility {
+        target_or_random: build_all_allies(),
+        ability: Box::new(build_nested_ability(depth)),
+    }
+}
+
+fn build_all_allies() -> emo::ability::TargetOrRandom {
+    emo::ability::TargetOrRandom::Target(emo::ability::Target::Others {
+        destination: emo::ability::Destination::All,
+        typ_and_triple: Default::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mtc::{
+        battle::organizer::battle_all, setup::build_pool,
+        shop::player_operation::verify_player_operations_and_update,
+    };
+
+    #[test]
+    fn test_worst_case_is_valid() {
+        let emo_bases = build_emo_bases();
+        let (built, fixed) = build_deck_base_ids();
+        let pool = build_pool(&built, &emo_bases, &fixed, &built).unwrap();
+        let history = build_grade_and_board_history(&emo_bases);
+        let turn = history.len() as u8 + 1;
+
+        let mut grade = GRADES;
+        let board = verify_player_operations_and_update(
+            history.last().unwrap().board.clone(),
+            &mut grade,
+            &mut None,
+            &build_player_operations(),
+            &pool,
+            1,
+            turn,
+            &emo_bases,
+        )
+        .unwrap();
+        assert_eq!(board.0.len(), BOARD_EMO_MAX_COUNT as usize);
+
+        let ghost = build_ghost(&emo_bases);
+        assert!(battle_all(
+            &board,
+            &mut 30,
+            &mut [
+                mtc::GhostState::Active { health: 30 },
+                mtc::GhostState::Active { health: 30 },
+                mtc::GhostState::Active { health: 30 },
+            ],
+            grade,
+            &[ghost.clone(), ghost.clone(), ghost],
+            0,
+            turn,
+            2,
+            &emo_bases,
+        )
+        .is_ok());
+    }
+}
@@ -1,13 +1,172 @@
+// the first 4 bytes of the hash must be less than the threshold, lower is harder
+pub const DEFAULT_THRESHOLD: u32 = 2655;
+
+#[cfg(all(feature = "chain", feature = "std"))]
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    thread,
+};
+
+#[cfg(all(feature = "chain", feature = "std"))]
+const SOLVE_CHUNK_SIZE: u64 = 1 << 14;
+
+#[cfg(feature = "chain")]
+const BLAKE2B_IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+#[cfg(feature = "chain")]
+const BLAKE2B_SIGMA: [[usize; 16]; 12] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+];
+
+// the input is a single 128 bytes blake2b block and only its last word, the solution, varies.
+// the first round reads the words in order, so all of it but the last mixing
+// is the same for every solution and computed once.
+// call_hash is the hash of the encoded call, so a solution is bound to a call and its args
+#[cfg(feature = "chain")]
+#[derive(Clone)]
+pub struct Puzzle {
+    m: [u64; 16],
+    v: [u64; 16], // the first round without the mixing of the solution
+    h0: u64,
+    threshold: u32,
+}
+
+#[cfg(feature = "chain")]
+impl Puzzle {
+    pub fn new(account: &[u8; 32], count: u32, call_hash: &[u8; 32], threshold: u32) -> Self {
+        let mut block = [0u8; 128];
+
+        block[0..32].copy_from_slice(account);
+        block[32..36].copy_from_slice(&count.to_le_bytes()[..]);
+        block[36] = 123;
+        block[37..69].copy_from_slice(call_hash);
+
+        let mut m = [0u64; 16];
+        for (w, chunk) in m.iter_mut().zip(block.chunks_exact(8)) {
+            *w = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+
+        // 32 bytes output, no key
+        let h0 = BLAKE2B_IV[0] ^ 0x01010020;
+        let mut v = [0u64; 16];
+        v[0] = h0;
+        v[1..8].copy_from_slice(&BLAKE2B_IV[1..]);
+        v[8..].copy_from_slice(&BLAKE2B_IV);
+        v[12] ^= 128; // the input length
+        v[14] = !v[14]; // the last block
+
+        g(&mut v, 0, 4, 8, 12, m[0], m[1]);
+        g(&mut v, 1, 5, 9, 13, m[2], m[3]);
+        g(&mut v, 2, 6, 10, 14, m[4], m[5]);
+        g(&mut v, 3, 7, 11, 15, m[6], m[7]);
+        g(&mut v, 0, 5, 10, 15, m[8], m[9]);
+        g(&mut v, 1, 6, 11, 12, m[10], m[11]);
+        g(&mut v, 2, 7, 8, 13, m[12], m[13]);
+
+        Self {
+            m,
+            v,
+            h0,
+            threshold,
+        }
+    }
+
+    pub fn check(&self, solution: u64) -> bool {
+        let mut m = self.m;
+        m[15] = solution;
+        let mut v = self.v;
+
+        g(&mut v, 3, 4, 9, 14, m[14], m[15]);
+        for s in &BLAKE2B_SIGMA[1..11] {
+            round(&mut v, &m, s);
+        }
+
+        // the compared first word of the hash only depends on v[0] and v[8],
+        // so the last round skips the two mixings that don't reach them
+        let s = &BLAKE2B_SIGMA[11];
+        g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+        g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+        g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+        g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+        g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+        g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+
+        ((self.h0 ^ v[0] ^ v[8]) as u32) < self.threshold
+    }
+
+    #[cfg(feature = "std")]
+    fn solve_range(&self, start: u64, end: u64) -> Option<u64> {
+        (start..end).find(|&solution| self.check(solution))
+    }
+}
+
+#[cfg(feature = "chain")]
+#[inline(always)]
+fn round(v: &mut [u64; 16], m: &[u64; 16], s: &[usize; 16]) {
+    g(v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+    g(v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+    g(v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+    g(v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+    g(v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+    g(v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+    g(v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+    g(v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+}
+
+#[cfg(feature = "chain")]
+#[inline(always)]
+fn g(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
 #[cfg(feature = "chain")]
-const THRESHOLD: u32 = 2655;
+pub fn get_call_hash(encoded_call: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(blake2_rfc::blake2b::blake2b(32, &[], encoded_call).as_bytes());
+    hash
+}
 
 #[cfg(feature = "chain")]
-pub fn check_solution(account: &[u8; 32], count: u32, solution: u64) -> bool {
+pub fn check_solution(
+    account: &[u8; 32],
+    count: u32,
+    call_hash: &[u8; 32],
+    solution: u64,
+    threshold: u32,
+) -> bool {
     let mut input = [0u8; 128];
 
     input[0..32].copy_from_slice(account);
     input[32..36].copy_from_slice(&count.to_le_bytes()[..]);
     input[36] = 123;
+    input[37..69].copy_from_slice(call_hash);
     input[120..].copy_from_slice(&solution.to_le_bytes()[..]);
 
     let result = blake2_rfc::blake2b::blake2b(32, &[], &input);
@@ -15,41 +174,87 @@ pub fn check_solution(account: &[u8; 32], count: u32, solution: u64) -> bool {
 
     let n = u32::from_le_bytes([full_hash[0], full_hash[1], full_hash[2], full_hash[3]]);
 
-    n < THRESHOLD
+    n < threshold
 }
 
-// works, but poor performance
+// the smallest solution
 #[cfg(feature = "chain")]
-pub fn solve(account: &[u8; 32], count: u32) -> u64 {
-    let mut input = [0u8; 128];
+pub fn solve(account: &[u8; 32], count: u32, call_hash: &[u8; 32], threshold: u32) -> u64 {
+    let puzzle = Puzzle::new(account, count, call_hash, threshold);
+    (0..).find(|&solution| puzzle.check(solution)).unwrap()
+}
 
-    input[0..32].copy_from_slice(account);
-    input[32..36].copy_from_slice(&count.to_le_bytes()[..]);
-    input[36] = 123;
+// any solution, or none if cancelled.
+// on_progress receives the number of the checked solutions after each chunk
+#[cfg(all(feature = "chain", feature = "std"))]
+pub fn solve_parallel(
+    account: &[u8; 32],
+    count: u32,
+    call_hash: &[u8; 32],
+    threshold: u32,
+    threads: usize,
+    on_progress: &(dyn Fn(u64) + Sync),
+    cancelled: &AtomicBool,
+) -> Option<u64> {
+    let puzzle = Puzzle::new(account, count, call_hash, threshold);
+    let next_chunk = AtomicU64::new(0);
+    let checked = AtomicU64::new(0);
+    let found = AtomicBool::new(false);
 
-    let mut solution = 0u64;
+    let worker = || {
+        while !cancelled.load(Ordering::Relaxed) && !found.load(Ordering::Relaxed) {
+            let start = next_chunk.fetch_add(1, Ordering::Relaxed) * SOLVE_CHUNK_SIZE;
 
-    loop {
-        input[120..].copy_from_slice(&solution.to_le_bytes()[..]);
+            if let Some(solution) = puzzle.solve_range(start, start + SOLVE_CHUNK_SIZE) {
+                found.store(true, Ordering::Relaxed);
+                return Some(solution);
+            }
 
-        let result = blake2_rfc::blake2b::blake2b(32, &[], &input);
-        let full_hash = result.as_bytes();
+            on_progress(checked.fetch_add(SOLVE_CHUNK_SIZE, Ordering::Relaxed) + SOLVE_CHUNK_SIZE);
+        }
+        None
+    };
 
-        let n = u32::from_le_bytes([full_hash[0], full_hash[1], full_hash[2], full_hash[3]]);
+    thread::scope(|s| {
+        let handles = (0..threads.max(1))
+            .map(|_| s.spawn(worker))
+            .collect::<Vec<_>>();
 
-        if n < THRESHOLD {
-            return solution;
-        }
+        handles
+            .into_iter()
+            .filter_map(|h| h.join().expect("pow solver thread panicked"))
+            .min()
+    })
+}
 
-        solution += 1;
-    }
+#[cfg(all(feature = "chain", feature = "std"))]
+pub fn get_solve_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// keeps the expected number of pow transactions per period around the target
+pub fn adjust_threshold(threshold: u32, count: u32, target: u32) -> u32 {
+    let adjusted = threshold as u64 * target.max(1) as u64 / count.max(1) as u64;
+
+    adjusted.clamp(
+        (threshold / 4).max(1) as u64,
+        threshold.saturating_mul(4) as u64,
+    ) as u32
 }
 
-// fn difficulty_to_threshold(difficulty: u8) -> u32 {
-//     //let d = 175.0;
-//     let d = difficulty as f64;
-//     2.0f64.powf((255.999-d)/8.0).floor() as u32
-// }
+// each pow transaction in the window beyond free_count multiplies the difficulty
+pub fn get_quota_threshold(
+    threshold: u32,
+    used: u32,
+    free_count: u32,
+    difficulty_multiplier: u32,
+) -> u32 {
+    let divisor = difficulty_multiplier
+        .max(1)
+        .saturating_pow(used.saturating_sub(free_count));
+
+    (threshold / divisor).max(1)
+}
 
 #[cfg(feature = "chain")]
 #[cfg(test)]
@@ -62,8 +267,97 @@ mod tests {
         account[..3].copy_from_slice(&[1, 2, 3]);
         let count = 1;
         let solution = u64::from_le_bytes([0, 0, 0, 0, 94, 95, 51, 0]);
-        let b = check_solution(&account, count, solution);
+        let b = check_solution(&account, count, &[0; 32], solution, DEFAULT_THRESHOLD);
         assert!(b);
+        assert!(!check_solution(&account, count, &[0; 32], solution, 1));
+        assert!(!check_solution(
+            &account,
+            count,
+            &get_call_hash(&[0, 1]),
+            solution,
+            DEFAULT_THRESHOLD
+        ));
+    }
+
+    #[test]
+    fn test_puzzle_check() {
+        let account = [7u8; 32];
+        let call_hash = get_call_hash(&[4, 5, 6]);
+
+        for threshold in [DEFAULT_THRESHOLD, u32::MAX / 2] {
+            let puzzle = Puzzle::new(&account, 3, &call_hash, threshold);
+            for solution in (0..1 << 12).chain([u64::MAX]) {
+                assert_eq!(
+                    puzzle.check(solution),
+                    check_solution(&account, 3, &call_hash, solution, threshold)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_adjust_threshold() {
+        assert_eq!(adjust_threshold(2655, 100, 100), 2655);
+        assert_eq!(adjust_threshold(2655, 200, 100), 1327);
+        assert_eq!(adjust_threshold(2655, 50, 100), 5310);
+        assert_eq!(adjust_threshold(2655, 0, 100), 10620);
+        assert_eq!(adjust_threshold(2655, 10000, 100), 663);
+        assert_eq!(adjust_threshold(1, 10000, 100), 1);
+        assert_eq!(adjust_threshold(u32::MAX, 1, 100), u32::MAX);
+    }
+
+    #[test]
+    fn test_get_quota_threshold() {
+        assert_eq!(get_quota_threshold(2655, 0, 2, 4), 2655);
+        assert_eq!(get_quota_threshold(2655, 2, 2, 4), 2655);
+        assert_eq!(get_quota_threshold(2655, 3, 2, 4), 663);
+        assert_eq!(get_quota_threshold(2655, 4, 2, 4), 165);
+        assert_eq!(get_quota_threshold(2655, 100, 2, 4), 1);
+        assert_eq!(get_quota_threshold(2655, 100, 2, 1), 2655);
+        assert_eq!(get_quota_threshold(2655, 100, 2, 0), 2655);
+    }
+
+    #[test]
+    fn test_solve_parallel() {
+        let account = [1u8; 32];
+        let call_hash = get_call_hash(&[2, 3]);
+        let threshold = DEFAULT_THRESHOLD * 1024;
+
+        let solution = solve_parallel(
+            &account,
+            1,
+            &call_hash,
+            threshold,
+            4,
+            &|_| {},
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+        assert!(check_solution(&account, 1, &call_hash, solution, threshold));
+        // the same call with other args
+        assert!(!check_solution(
+            &account,
+            1,
+            &get_call_hash(&[2, 3, 1]),
+            solution,
+            threshold
+        ));
+
+        let cancelled = AtomicBool::new(false);
+        let solution = solve_parallel(
+            &account,
+            1,
+            &call_hash,
+            1,
+            2,
+            &|checked| {
+                if checked >= SOLVE_CHUNK_SIZE * 4 {
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+            },
+            &cancelled,
+        );
+        assert_eq!(solution, None);
     }
 
     #[test]
@@ -72,7 +366,13 @@ mod tests {
         let mut account = [0u8; 32];
         account[..3].copy_from_slice(&[1, 2, 3]);
         let count = 1;
-        let solution = solve(&account, count);
-        assert!(check_solution(&account, count, solution));
+        let solution = solve(&account, count, &[0; 32], DEFAULT_THRESHOLD);
+        assert!(check_solution(
+            &account,
+            count,
+            &[0; 32],
+            solution,
+            DEFAULT_THRESHOLD
+        ));
     }
 }
@@ -3,10 +3,12 @@ import { resolve } from "path"
 
 import { ContractPromise } from "@polkadot/api-contract"
 import type { IKeyringPair } from "@polkadot/types/types"
+import { blake2AsU8a } from "@polkadot/util-crypto"
 import { createType, connect, range, txContract, sampleArray } from "common"
 import { getEndpointAndPair } from "../utils"
 
 const randomAddress = "5Hasqa9swLcoKGyFikR1su9bdrqkRhtitSNb3iNKEwgk5UGf"
+const seedSecret = new Uint8Array(32)
 
 const main = async () => {
   const { endpoint, keyringPair } = await getEndpointAndPair()
@@ -39,7 +41,7 @@ const setup = async (
     ["removeMatchmakingGhostsInfo", [1]],
     ["removeMatchmakingGhostsInfo", [2]],
     ["removeMatchmakingGhostsInfo", [3]],
-    ["insertMatchmakingGhostsInfo", [0, range(20).map(() => [0, randomAddress])]],
+    ["insertMatchmakingGhostsInfo", [0, range(20).map(() => [0, randomAddress, 0])]],
     ...range(20).map((i) => ["insertMatchmakingGhostByIndex", [0, i, buildGhost()]] as const),
   ] as const
 
@@ -54,7 +56,13 @@ const setup = async (
 const start = async (contract: ContractPromise, keyringPair: IKeyringPair) => {
   console.log("start")
 
-  await txContract(contract, "startMtc", [[26, 44, 59, 48, 52, 12]], keyringPair)
+  await txContract(contract, "commitSeed", [blake2AsU8a(seedSecret, 256)], keyringPair)
+  await txContract(
+    contract,
+    "startMtc",
+    [[26, 44, 59, 48, 52, 12], [], seedSecret, blake2AsU8a(seedSecret, 256)],
+    keyringPair
+  )
 }
 
 const finish = async (
@@ -90,7 +98,12 @@ const finish = async (
   })
 
   await txContract(contract, "insertPlayerMtcMutable", [playerAddress, m], keyringPair)
-  await txContract(contract, "finishMtcShop", [buildPlayerOperations()], keyringPair)
+  await txContract(
+    contract,
+    "finishMtcShop",
+    [buildPlayerOperations(), seedSecret, blake2AsU8a(seedSecret, 256)],
+    keyringPair
+  )
 }
 
 const buildGhost = () => {
@@ -27,32 +27,17 @@ pub fn update_player_mtc_mutable_after_battle(
     .expect("battle ghost selection failed");
 }
 
-pub fn calc_new_ep(place: u8, old_ep: u16) -> u16 {
-    if let Some(plus) = match place {
-        1 => Some(70),
-        2 => Some(50),
-        _ => None,
-    } {
-        return (if old_ep > ep::INITIAL_EP {
-            let x = (old_ep - ep::INITIAL_EP) / 40;
-            if x < plus {
-                plus - x
-            } else {
-                1
-            }
+// moves the ep halfway toward the initial ep for each season
+pub fn soft_reset_ep(ep: u16, seasons: u32) -> u16 {
+    let mut ep = ep;
+    for _ in 0..seasons.min(16) {
+        if ep > ep::INITIAL_EP {
+            ep -= (ep - ep::INITIAL_EP) / 2;
         } else {
-            plus
-        })
-        .saturating_add(old_ep);
+            ep += (ep::INITIAL_EP - ep) / 2;
+        }
     }
-
-    let minus = match place {
-        3 => 30,
-        4 => 50,
-        _ => panic!("unsupported place: {}", place),
-    };
-
-    ep::reduce_ep(old_ep, minus)
+    ep
 }
 
 const LEADERBOARD_SIZE: u8 = 100;
@@ -106,6 +91,47 @@ pub fn update_leaderboard<A: Eq + Copy>(
     Some(leaderboard)
 }
 
+pub const GHOST_ARCHIVE_SIZE: u32 = 50;
+pub const GHOST_ARCHIVE_PAGE_SIZE: u32 = 10;
+
+// newest first, only sequence numbers which are still retained
+pub fn get_ghost_archive_page_seqs(len: u32, page: u32) -> Vec<u32> {
+    let retained = len.min(GHOST_ARCHIVE_SIZE);
+    let skip = page.saturating_mul(GHOST_ARCHIVE_PAGE_SIZE);
+
+    if skip >= retained {
+        return vec![];
+    }
+
+    let end = len - skip;
+    let start = end.saturating_sub(GHOST_ARCHIVE_PAGE_SIZE.min(retained - skip));
+
+    (start..end).rev().collect()
+}
+
+pub const MAX_RECENT_OPPONENTS_MTC_LEN: u8 = 10;
+
+// the counts of the emos on the final boards, most counted first.
+// every base is kept, so the length is bounded by the number of emo bases
+pub fn update_emo_counts(mut counts: Vec<(u16, u32)>, board: &mtc::Board) -> Vec<(u16, u32)> {
+    for emo in board.0.iter() {
+        if let Some((_, count)) = counts.iter_mut().find(|(id, _)| *id == emo.base_id) {
+            *count = count.saturating_add(1);
+        } else {
+            counts.push((emo.base_id, 1));
+        }
+    }
+
+    counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+    counts
+}
+
+// a lower place, then a higher grade
+pub fn is_better_result(place: u8, grade: u8, best_place: u8, best_grade: u8) -> bool {
+    place < best_place || (place == best_place && grade > best_grade)
+}
+
 pub fn build_initial_ghost_states(ep: u16) -> Vec<mtc::GhostState> {
     let health = match ep::get_ep_band(ep) {
         0 => 14,
@@ -127,14 +153,61 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_calc_new_ep() {
-        assert_eq!(calc_new_ep(1, 50), 120);
-        assert_eq!(calc_new_ep(2, 50), 100);
-        assert_eq!(calc_new_ep(3, 50), 20);
-        assert_eq!(calc_new_ep(4, 50), 1);
-
-        assert_eq!(calc_new_ep(1, 500), 565);
-        assert_eq!(calc_new_ep(2, 2700), 2701);
+    fn test_update_emo_counts() {
+        fn build_board(base_ids: &[u16]) -> mtc::Board {
+            mtc::Board(
+                base_ids
+                    .iter()
+                    .map(|&base_id| mtc::BoardEmo {
+                        base_id,
+                        ..Default::default()
+                    })
+                    .collect(),
+            )
+        }
+
+        let counts = update_emo_counts(vec![], &build_board(&[1, 2, 2]));
+        assert_eq!(counts, vec![(2, 2), (1, 1)]);
+
+        let counts = update_emo_counts(counts, &build_board(&[1, 1, 3]));
+        assert_eq!(counts, vec![(1, 3), (2, 2), (3, 1)]);
+
+        let counts = update_emo_counts(counts, &Default::default());
+        assert_eq!(counts, vec![(1, 3), (2, 2), (3, 1)]);
+
+        // a new emo still gets in after many distinct emos
+        let many = (0..100).map(|id| (id, 2)).collect::<Vec<_>>();
+        let counts = update_emo_counts(many, &build_board(&[1000, 5]));
+        assert_eq!(counts.len(), 101);
+        assert_eq!(counts[0], (5, 3));
+        assert_eq!(counts[100], (1000, 1));
+
+        let counts = update_emo_counts(counts, &build_board(&[1000, 1000, 1000]));
+        assert_eq!(counts[0], (1000, 4));
+    }
+
+    #[test]
+    fn test_is_better_result() {
+        assert!(is_better_result(1, 1, 2, 6));
+        assert!(is_better_result(2, 4, 2, 3));
+        assert!(!is_better_result(2, 3, 2, 3));
+        assert!(!is_better_result(3, 6, 2, 1));
+    }
+
+    #[test]
+    fn test_soft_reset_ep() {
+        assert_eq!(soft_reset_ep(500, 0), 500);
+        assert_eq!(soft_reset_ep(500, 1), 400);
+        assert_eq!(soft_reset_ep(500, 2), 350);
+        assert_eq!(soft_reset_ep(301, 1), 301);
+        assert_eq!(soft_reset_ep(300, 1), 300);
+        assert_eq!(soft_reset_ep(100, 1), 200);
+        assert_eq!(soft_reset_ep(1, 1), 150);
+        assert_eq!(soft_reset_ep(u16::MAX, 1), 32918);
+        assert_eq!(
+            soft_reset_ep(u16::MAX, u32::MAX),
+            soft_reset_ep(u16::MAX, 16)
+        );
     }
 
     #[test]
@@ -302,6 +375,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_ghost_archive_page_seqs() {
+        assert_eq!(get_ghost_archive_page_seqs(0, 0), vec![]);
+        assert_eq!(get_ghost_archive_page_seqs(3, 0), vec![2, 1, 0]);
+        assert_eq!(get_ghost_archive_page_seqs(3, 1), vec![]);
+        assert_eq!(
+            get_ghost_archive_page_seqs(12, 0),
+            (2..12).rev().collect::<Vec<_>>()
+        );
+        assert_eq!(get_ghost_archive_page_seqs(12, 1), vec![1, 0]);
+
+        assert_eq!(
+            get_ghost_archive_page_seqs(55, 0),
+            (45..55).rev().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            get_ghost_archive_page_seqs(55, 4),
+            (5..15).rev().collect::<Vec<_>>()
+        );
+        assert_eq!(get_ghost_archive_page_seqs(55, 5), vec![]);
+        assert_eq!(get_ghost_archive_page_seqs(57, 4).last(), Some(&7));
+        assert_eq!(get_ghost_archive_page_seqs(u32::MAX, u32::MAX), vec![]);
+    }
+
     #[test]
     fn test_build_initial_ghost_states() {
         assert_eq!(
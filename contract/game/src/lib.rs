@@ -10,7 +10,15 @@ pub mod contract {
     use ink::storage::Mapping;
     use scale::{Decode, Encode};
 
-    type PlayerImmutable = (Vec<mtc::Emo>, Vec<Option<(AccountId, mtc::Ghost)>>); // (pool, ghosts)
+    // the seed is mixed with the random pool at the end of the block this many blocks after the commit,
+    // so the secret is revealed after that block
+    const SEED_ENTROPY_DELAY: BlockNumber = 1;
+    // the secret is revealed within this period from the commit block,
+    // an expired secret can only be replaced by commit_seed, which forfeits the mtc
+    const SEED_REVEAL_PERIOD: BlockNumber = 300;
+
+    type PlayerImmutable = (Vec<mtc::Emo>, Vec<(Option<AccountId>, u16, mtc::Ghost)>); // (pool, ghosts with the eps they are rated by), bot ghosts have no account
+    type LegacyPlayerImmutable = (Vec<mtc::Emo>, Vec<Option<(AccountId, mtc::Ghost)>>); // (pool, ghosts), the layout before bot ghosts
 
     #[derive(PartialEq, Eq, Clone, Debug, Encode, Decode)]
     #[cfg_attr(
@@ -19,6 +27,10 @@ pub mod contract {
     )]
     enum LazyStorageKey {
         Leaderboard,
+        Season,
+        PastSeason(u32),
+        RandomPool,
+        MatchmakingConfig,
     }
 
     #[derive(PartialEq, Eq, Clone, Debug, Encode, Decode)]
@@ -28,6 +40,220 @@ pub mod contract {
     )]
     enum LazyStorageValue {
         Leaderboard(Vec<(u16, AccountId)>),
+        Season(Season),
+        PastSeason(PastSeason),
+        RandomPool([u8; 32], BlockNumber), // (pool, updated block number)
+        MatchmakingConfig(MatchmakingConfig),
+    }
+
+    // the block number fixes the random pool mixed into the seed, unknown when the secret is committed
+    #[derive(PartialEq, Eq, Clone, Debug, Encode, Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct SeedCommitment {
+        pub hash: [u8; 32], // blake2x256 hash of the secret
+        pub block_number: BlockNumber,
+    }
+
+    #[derive(Default, PartialEq, Eq, Clone, Debug, Encode, Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Season {
+        pub id: u32,
+        pub start_block: BlockNumber,
+        pub duration: Option<BlockNumber>, // rolls over automatically if some
+    }
+
+    #[derive(PartialEq, Eq, Clone, Debug, Encode, Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct PastSeason {
+        pub start_block: BlockNumber,
+        pub end_block: BlockNumber,
+        pub leaderboard: Vec<(u16, AccountId)>,
+    }
+
+    #[derive(PartialEq, Eq, Clone, Debug, Encode, Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct MatchmakingConfig {
+        pub recent_opponents_mtc_len: u8, // the opponents of the last n mtcs are excluded
+        pub upward_band_search_len: u16,
+        pub recency_half_life: BlockNumber, // 0 disables the recency weighting
+    }
+
+    impl Default for MatchmakingConfig {
+        fn default() -> Self {
+            Self {
+                recent_opponents_mtc_len: 3,
+                upward_band_search_len: 2,
+                recency_half_life: 50_400,
+            }
+        }
+    }
+
+    #[derive(PartialEq, Eq, Clone, Debug, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum GhostSource {
+        Matchmaking { ep_band: u16, index: u8 },
+        Account { ep_band: u16, account: AccountId },
+        // a ghost not registered by a player, recorded with no account
+        Ghost { ghost: mtc::Ghost },
+        Archive { account: AccountId, seq: u32 },
+    }
+
+    #[derive(PartialEq, Eq, Clone, Debug, Encode, Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct ArchivedGhost {
+        pub ghost: mtc::Ghost,
+        pub ep: u16,
+        pub place: u8,
+        pub block_number: BlockNumber,
+    }
+
+    #[derive(PartialEq, Eq, Clone, Debug, Encode, Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct MatchResult {
+        pub place: u8,
+        pub old_ep: u16,
+        pub new_ep: u16,
+        pub grade: u8,
+        pub board: mtc::Board,
+        pub turn: u8,
+        pub ghost_accounts: Vec<Option<AccountId>>,
+        pub seed: u64, // the seed of the last battle
+    }
+
+    // lifetime stats of the mtcs except challenges
+    #[derive(PartialEq, Eq, Clone, Debug, Default, Encode, Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct PlayerStats {
+        pub played_count: u32,
+        pub place_counts: [u32; 4],
+        pub highest_ep: u16,
+        pub emo_counts: Vec<(u16, u32)>, // (emo base id, count on the final boards), most counted first
+        pub best_result: Option<MatchResult>,
+        pub win_streak: u32,
+        pub max_win_streak: u32,
+    }
+
+    #[derive(PartialEq, Eq, Clone, Debug, Encode, Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Session {
+        pub account: AccountId,
+        pub expiry_block: BlockNumber,
+    }
+
+    // a cosmetic variant of an emo base, never affects the attributes
+    #[derive(PartialEq, Eq, Clone, Debug, Encode, Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Skin {
+        pub base_id: u16,
+        pub codepoint: Option<u32>, // replaces the codepoint of the base
+        pub frame: u8,              // 0 is no frame
+    }
+
+    #[ink(event)]
+    pub struct MtcStarted {
+        #[ink(topic)]
+        player: AccountId,
+        ep: u16,
+        is_challenge: bool,
+        ghost_accounts: Vec<Option<AccountId>>,
+    }
+
+    #[ink(event)]
+    pub struct ShopFinished {
+        #[ink(topic)]
+        player: AccountId,
+        turn: u8,
+        grade: u8,
+        health: u8,
+        ghost_states: Vec<mtc::GhostState>,
+    }
+
+    #[ink(event)]
+    pub struct MtcFinished {
+        #[ink(topic)]
+        player: AccountId,
+        result: MatchResult,
+    }
+
+    #[ink(event)]
+    pub struct AchievementsEarned {
+        #[ink(topic)]
+        player: AccountId,
+        ids: Vec<u16>,
+    }
+
+    #[ink(event)]
+    pub struct SessionSet {
+        #[ink(topic)]
+        player: AccountId,
+        #[ink(topic)]
+        session: AccountId,
+        expiry_block: BlockNumber,
+    }
+
+    #[ink(event)]
+    pub struct SessionRevoked {
+        #[ink(topic)]
+        player: AccountId,
+        #[ink(topic)]
+        session: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct EmoBasesUpdated {
+        fixed_base_ids: Vec<u16>,
+        built_base_ids: Vec<u16>,
+        force_bases_update: bool,
+    }
+
+    #[ink(event)]
+    pub struct SeasonStarted {
+        id: u32,
+        start_block: BlockNumber,
+    }
+
+    #[ink(event)]
+    pub struct AdminAdded {
+        #[ink(topic)]
+        account: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct AdminRemoved {
+        #[ink(topic)]
+        account: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct CodeUpgraded {
+        code_hash: [u8; 32],
     }
 
     #[ink(storage)]
@@ -39,17 +265,42 @@ pub mod contract {
         deck_fixed_emo_base_ids: Option<Vec<u16>>,
         deck_built_emo_base_ids: Option<Vec<u16>>,
 
-        matchmaking_ghosts_info: Mapping<u16, Vec<(BlockNumber, AccountId)>>,
+        matchmaking_ghosts_info: Mapping<u16, Vec<(BlockNumber, AccountId)>>, // the layout before the registered ep, moved to v2 when the band is updated
+        matchmaking_ghosts_info_v2: Mapping<u16, Vec<(BlockNumber, AccountId, u16)>>, // (registered block number, account, registered ep)
         matchmaking_ghost_by_index: Mapping<(u16, u8), mtc::Ghost>,
 
         lazy: Mapping<LazyStorageKey, LazyStorageValue>,
+        random_pool_snapshots: Mapping<BlockNumber, (BlockNumber, [u8; 32])>, // block number % SEED_REVEAL_PERIOD -> (block number, pool at the end of the block)
+
+        skins: Mapping<u16, Skin>,
+        skin_owners: Mapping<u16, Vec<AccountId>>, // the accounts in player_skins for the skin id
 
         player_ep: Mapping<AccountId, u16>,
+        player_ep_season: Mapping<AccountId, u32>, // the season id when player_ep is updated
+        player_rd: Mapping<AccountId, (u16, BlockNumber)>, // (rating deviation, updated block number)
         player_seed: Mapping<AccountId, u64>,
+        player_match_result: Mapping<AccountId, MatchResult>,
+        player_stats: Mapping<AccountId, PlayerStats>,
+        player_achievements: Mapping<AccountId, Vec<u16>>, // achievement ids in the earned order
+        player_seed_commitment: Mapping<AccountId, SeedCommitment>,
+        player_recent_opponents: Mapping<AccountId, Vec<AccountId>>,
+        player_skins: Mapping<(AccountId, u16), ()>, // (account, skin id)
+
+        // player storage is keyed by the main account, sessions can only play mtc
+        player_main_to_session: Mapping<AccountId, Session>,
+        player_session_to_main: Mapping<AccountId, AccountId>,
+        player_session_offer: Mapping<AccountId, (AccountId, BlockNumber)>, // session -> (main, expiry block)
+
+        player_ghost_archive_enabled: Mapping<AccountId, ()>,
+        player_ghost_archive_len: Mapping<AccountId, u32>,
+        player_ghost_archive: Mapping<(AccountId, u32), ArchivedGhost>, // (account, len % GHOST_ARCHIVE_SIZE)
 
         // remove on each mtc
-        player_mtc_immutable: Mapping<AccountId, PlayerImmutable>,
+        player_mtc_immutable: Mapping<AccountId, LegacyPlayerImmutable>, // the mtcs started before the upgrade to v2
+        player_mtc_immutable_v2: Mapping<AccountId, PlayerImmutable>,
         player_mtc_mutable: Mapping<AccountId, mtc::storage::PlayerMutable>,
+        player_mtc_challenge: Mapping<AccountId, ()>,
+        player_mtc_skins: Mapping<AccountId, Vec<u16>>, // skin ids selected in start_mtc
     }
 
     impl Contract {
@@ -69,12 +320,18 @@ pub mod contract {
         pub fn add_admin(&mut self, account_id: AccountId) {
             self.assert_admin();
             self.admins.push(account_id);
+            self.env().emit_event(AdminAdded {
+                account: account_id,
+            });
         }
 
         #[ink(message)]
         pub fn remove_admin(&mut self, account_id: AccountId) {
             self.assert_admin();
             self.admins.retain(|a| a != &account_id);
+            self.env().emit_event(AdminRemoved {
+                account: account_id,
+            });
         }
 
         #[ink(message)]
@@ -87,6 +344,7 @@ pub mod contract {
                     code_hash, err
                 )
             });
+            self.env().emit_event(CodeUpgraded { code_hash });
         }
 
         #[ink(message)]
@@ -108,8 +366,8 @@ pub mod contract {
         pub fn get_matchmaking_ghosts_info(
             &self,
             ep_band: u16,
-        ) -> Option<Vec<(BlockNumber, AccountId)>> {
-            self.matchmaking_ghosts_info.get(ep_band)
+        ) -> Option<Vec<(BlockNumber, AccountId, u16)>> {
+            ghost::MatchmakingStorage::get_ghosts_info(self, ep_band)
         }
 
         #[ink(message)]
@@ -123,16 +381,72 @@ pub mod contract {
 
         #[ink(message)]
         pub fn get_leaderboard(&self) -> Vec<(u16, AccountId)> {
-            if let Some(value) = self.lazy.get(LazyStorageKey::Leaderboard) {
-                let LazyStorageValue::Leaderboard(leaderboard) = value;
+            if let Some(LazyStorageValue::Leaderboard(leaderboard)) =
+                self.lazy.get(LazyStorageKey::Leaderboard)
+            {
                 return leaderboard;
             }
             vec![]
         }
 
+        #[ink(message)]
+        pub fn get_matchmaking_config(&self) -> MatchmakingConfig {
+            if let Some(LazyStorageValue::MatchmakingConfig(config)) =
+                self.lazy.get(LazyStorageKey::MatchmakingConfig)
+            {
+                return config;
+            }
+            Default::default()
+        }
+
+        #[ink(message)]
+        pub fn get_player_recent_opponents(&self, account: AccountId) -> Vec<AccountId> {
+            self.player_recent_opponents
+                .get(account)
+                .unwrap_or_default()
+        }
+
+        #[ink(message)]
+        pub fn get_player_seed_commitment(&self, account: AccountId) -> Option<SeedCommitment> {
+            self.player_seed_commitment.get(account)
+        }
+
+        #[ink(message)]
+        pub fn get_season(&self) -> Season {
+            if let Some(LazyStorageValue::Season(season)) = self.lazy.get(LazyStorageKey::Season) {
+                return season;
+            }
+            Default::default()
+        }
+
+        #[ink(message)]
+        pub fn get_past_season(&self, id: u32) -> Option<PastSeason> {
+            if let Some(LazyStorageValue::PastSeason(past_season)) =
+                self.lazy.get(LazyStorageKey::PastSeason(id))
+            {
+                return Some(past_season);
+            }
+            None
+        }
+
+        // the ep is soft-reset lazily for each season passed since its last update
         #[ink(message)]
         pub fn get_player_ep(&self, account: AccountId) -> Option<u16> {
-            self.player_ep.get(account)
+            self.player_ep.get(account).map(|ep| {
+                soft_reset_ep(
+                    ep,
+                    self.get_season()
+                        .id
+                        .saturating_sub(self.player_ep_season.get(account).unwrap_or(0)),
+                )
+            })
+        }
+
+        // the rating deviation including the increase by inactivity
+        #[ink(message)]
+        pub fn get_player_rd(&self, account: AccountId) -> u16 {
+            let (rd, periods) = self.get_player_rd_and_periods(account);
+            ep::increase_rd(rd, periods)
         }
 
         #[ink(message)]
@@ -140,9 +454,84 @@ pub mod contract {
             self.player_seed.get(account)
         }
 
+        #[ink(message)]
+        pub fn get_player_match_result(&self, account: AccountId) -> Option<MatchResult> {
+            self.player_match_result.get(account)
+        }
+
+        #[ink(message)]
+        pub fn get_player_stats(&self, account: AccountId) -> Option<PlayerStats> {
+            self.player_stats.get(account)
+        }
+
+        #[ink(message)]
+        pub fn get_player_achievements(&self, account: AccountId) -> Vec<u16> {
+            self.player_achievements.get(account).unwrap_or_default()
+        }
+
+        #[ink(message)]
+        pub fn get_earnable_achievement_ids(&self) -> Vec<u16> {
+            achievement::get_earnable_achievements(false)
+                .map(|a| a.id)
+                .collect()
+        }
+
+        #[ink(message)]
+        pub fn get_skin(&self, skin_id: u16) -> Option<Skin> {
+            self.skins.get(skin_id)
+        }
+
+        #[ink(message)]
+        pub fn get_player_skin_owned(&self, account: AccountId, skin_id: u16) -> bool {
+            self.player_skins.contains((account, skin_id))
+        }
+
+        #[ink(message)]
+        pub fn get_player_ghost_archive_enabled(&self, account: AccountId) -> bool {
+            self.player_ghost_archive_enabled.contains(account)
+        }
+
+        #[ink(message)]
+        pub fn get_player_ghost_archive_len(&self, account: AccountId) -> u32 {
+            self.player_ghost_archive_len.get(account).unwrap_or(0)
+        }
+
+        // newest first, each element is (sequence number, archived ghost)
+        #[ink(message)]
+        pub fn get_player_ghost_archive(
+            &self,
+            account: AccountId,
+            page: u32,
+        ) -> Vec<(u32, ArchivedGhost)> {
+            get_ghost_archive_page_seqs(self.get_player_ghost_archive_len(account), page)
+                .into_iter()
+                .filter_map(|seq| {
+                    self.player_ghost_archive
+                        .get((account, seq % GHOST_ARCHIVE_SIZE))
+                        .map(|g| (seq, g))
+                })
+                .collect()
+        }
+
         #[ink(message)]
         pub fn get_player_mtc_immutable(&self, account: AccountId) -> Option<PlayerImmutable> {
-            self.player_mtc_immutable.get(account)
+            self.player_mtc_immutable_v2.get(account).or_else(|| {
+                // a missing ghost was an empty ghost, and a ghost was rated by the owner's ep
+                self.player_mtc_immutable
+                    .get(account)
+                    .map(|(pool, ghosts)| {
+                        let ghosts = ghosts
+                            .into_iter()
+                            .map(|g| match g {
+                                Some((a, g)) => {
+                                    (Some(a), self.get_player_ep(a).unwrap_or(ep::INITIAL_EP), g)
+                                }
+                                None => (None, ep::INITIAL_EP, Default::default()),
+                            })
+                            .collect();
+                        (pool, ghosts)
+                    })
+            })
         }
 
         #[ink(message)]
@@ -153,6 +542,93 @@ pub mod contract {
             self.player_mtc_mutable.get(account)
         }
 
+        #[ink(message)]
+        pub fn get_player_mtc_challenge(&self, account: AccountId) -> bool {
+            self.player_mtc_challenge.contains(account)
+        }
+
+        #[ink(message)]
+        pub fn get_player_mtc_skins(&self, account: AccountId) -> Vec<u16> {
+            self.player_mtc_skins.get(account).unwrap_or_default()
+        }
+
+        // none removes the skin and its ownership
+        #[ink(message)]
+        pub fn set_skin(&mut self, skin_id: u16, skin: Option<Skin>) {
+            self.assert_admin();
+
+            match skin {
+                Some(skin) => {
+                    assert!(
+                        self.emo_bases
+                            .as_ref()
+                            .expect("emo_bases none")
+                            .find(skin.base_id)
+                            .is_ok(),
+                        "set_skin: emo base not found"
+                    );
+                    self.skins.insert(skin_id, &skin);
+                }
+                None => {
+                    self.skins.remove(skin_id);
+                    for account in self.skin_owners.take(skin_id).unwrap_or_default() {
+                        self.player_skins.remove((account, skin_id));
+                    }
+                }
+            }
+        }
+
+        #[ink(message)]
+        pub fn grant_skin(&mut self, account: AccountId, skin_id: u16) {
+            self.assert_admin();
+            assert!(self.skins.contains(skin_id), "grant_skin: skin not found");
+            if self.player_skins.insert((account, skin_id), &()).is_none() {
+                let mut owners = self.skin_owners.get(skin_id).unwrap_or_default();
+                owners.push(account);
+                self.skin_owners.insert(skin_id, &owners);
+            }
+        }
+
+        #[ink(message)]
+        pub fn revoke_skin(&mut self, account: AccountId, skin_id: u16) {
+            self.assert_admin();
+            if self.player_skins.take((account, skin_id)).is_some() {
+                let mut owners = self.skin_owners.get(skin_id).unwrap_or_default();
+                owners.retain(|a| a != &account);
+                self.skin_owners.insert(skin_id, &owners);
+            }
+        }
+
+        #[ink(message)]
+        pub fn set_season_duration(&mut self, duration: Option<BlockNumber>) {
+            self.assert_admin();
+
+            let mut season = self.get_season();
+            season.duration = duration;
+            self.set_season(season);
+        }
+
+        #[ink(message)]
+        pub fn set_matchmaking_config(&mut self, config: MatchmakingConfig) {
+            self.assert_admin();
+
+            assert!(
+                config.recent_opponents_mtc_len <= MAX_RECENT_OPPONENTS_MTC_LEN,
+                "set_matchmaking_config: recent_opponents_mtc_len too large"
+            );
+
+            self.lazy.insert(
+                LazyStorageKey::MatchmakingConfig,
+                &LazyStorageValue::MatchmakingConfig(config),
+            );
+        }
+
+        #[ink(message)]
+        pub fn rollover_season(&mut self) {
+            self.assert_admin();
+            self.start_next_season();
+        }
+
         #[ink(message)]
         pub fn update_emo_bases(
             &mut self,
@@ -173,80 +649,239 @@ pub mod contract {
             .expect("update_emo_bases: invalig arg");
 
             self.emo_bases = Some(bases);
-            self.deck_fixed_emo_base_ids = Some(fixed_base_ids);
-            self.deck_built_emo_base_ids = Some(built_base_ids);
+            self.deck_fixed_emo_base_ids = Some(fixed_base_ids.clone());
+            self.deck_built_emo_base_ids = Some(built_base_ids.clone());
+
+            self.env().emit_event(EmoBasesUpdated {
+                fixed_base_ids,
+                built_base_ids,
+                force_bases_update,
+            });
+        }
+
+        #[ink(message)]
+        pub fn get_player_session(&self, account: AccountId) -> Option<Session> {
+            self.player_main_to_session.get(account)
+        }
+
+        #[ink(message)]
+        pub fn get_player_main(&self, session: AccountId) -> Option<AccountId> {
+            self.player_session_to_main.get(session)
+        }
+
+        #[ink(message)]
+        pub fn get_player_session_offer(
+            &self,
+            session: AccountId,
+        ) -> Option<(AccountId, BlockNumber)> {
+            self.player_session_offer.get(session)
         }
 
+        // the session is set when the session account accepts it,
+        // an unaccepted offer to the same session is replaced
         #[ink(message)]
-        pub fn start_mtc(&mut self, deck_emo_base_ids: [u16; 6]) {
+        pub fn set_session(&mut self, session: AccountId, duration: BlockNumber) {
             let player = self.env().caller();
-            let seed = self.get_insecure_random_seed(player, b"start_mtc");
+            self.assert_session_available(player, session);
 
-            let ep = self.create_or_update_player_ep(player);
+            let expiry_block = self.env().block_number().saturating_add(duration);
+            self.player_session_offer
+                .insert(session, &(player, expiry_block));
+        }
 
-            self.player_seed.insert(player, &seed);
+        // called by the session account, replaces the current session of the player if any
+        #[ink(message)]
+        pub fn accept_session(&mut self, player: AccountId) {
+            let session = self.env().caller();
 
-            self.player_mtc_immutable.insert(
-                player,
-                &(
-                    setup::build_pool(
-                        &deck_emo_base_ids,
-                        self.emo_bases.as_ref().expect("emo_bases none"),
-                        self.deck_fixed_emo_base_ids
-                            .as_ref()
-                            .expect("deck_fixed_emo_base_ids none"),
-                        self.deck_built_emo_base_ids
-                            .as_ref()
-                            .expect("deck_built_emo_base_ids none"),
-                    )
-                    .expect("failed to build player pool"),
-                    ghost::choose_ghosts(
-                        ep,
-                        seed,
-                        &|ep_band| {
-                            self.matchmaking_ghosts_info
-                                .get(ep_band)
-                                .map(|v| v.into_iter().map(|(_, a)| a).collect())
-                        },
-                        &|t| self.matchmaking_ghost_by_index.get(t),
-                    ),
-                ),
+            let (main, expiry_block) = self
+                .player_session_offer
+                .get(session)
+                .expect("accept_session: session offer none");
+            assert!(
+                main == player,
+                "accept_session: session is offered by another account"
             );
+            self.assert_session_available(player, session);
 
-            self.player_mtc_mutable.insert(
+            self.player_session_offer.remove(session);
+            self.remove_session(player);
+
+            self.player_main_to_session.insert(
                 player,
-                &mtc::storage::PlayerMutable {
-                    health: setup::PLAYER_INITIAL_HEALTH,
-                    grade_and_board_history: Vec::new(),
-                    upgrade_coin: shop::coin::get_upgrade_coin(2),
-                    ghost_states: build_initial_ghost_states(ep),
-                    battle_ghost_index: 0,
+                &Session {
+                    account: session,
+                    expiry_block,
                 },
             );
+            self.player_session_to_main.insert(session, &player);
+
+            self.env().emit_event(SessionSet {
+                player,
+                session,
+                expiry_block,
+            });
+        }
+
+        // callable by either the main account or the session
+        #[ink(message)]
+        pub fn revoke_session(&mut self) {
+            let caller = self.env().caller();
+            let player = self.player_session_to_main.get(caller).unwrap_or(caller);
+            assert!(self.remove_session(player), "revoke_session: session none");
         }
 
         #[ink(message)]
-        pub fn finish_mtc_shop(&mut self, player_operations: Vec<mtc::shop::PlayerOperation>) {
+        pub fn set_ghost_archive_enabled(&mut self, enabled: bool) {
             let player = self.env().caller();
 
-            let emo_bases = self.emo_bases.as_ref().expect("emo_bases none");
+            if enabled {
+                self.player_ghost_archive_enabled.insert(player, &());
+            } else {
+                self.player_ghost_archive_enabled.remove(player);
+            }
+        }
 
-            let (player_pool, player_ghosts) = self
-                .player_mtc_immutable
-                .get(player)
-                .expect("player_mtc_immutable none");
-            let mut player_mtc_mutable = self
-                .player_mtc_mutable
-                .get(player)
-                .expect("player_mtc_mutable none");
+        // the first commitment of the player, or the one after a lost secret.
+        // the other commitments are stored by start_mtc and finish_mtc_shop.
+        // the current mtc is forfeited since its seeds would be rerolled
+        #[ink(message)]
+        pub fn commit_seed(&mut self, seed_commitment: [u8; 32]) {
+            let player = self.get_player_by_caller();
 
-            let (turn, mut grade, mut board) =
-                get_turn_and_previous_grade_and_board(&player_mtc_mutable.grade_and_board_history);
+            if self.player_mtc_mutable.contains(player) {
+                self.forfeit_mtc(player);
+            }
 
-            board = shop::player_operation::verify_player_operations_and_update(
-                board,
-                &mut grade,
-                &mut player_mtc_mutable.upgrade_coin,
+            self.insert_seed_commitment(player, seed_commitment);
+        }
+
+        // seed_secret must match the last commitment,
+        // next_seed_commitment is the blake2x256 hash of the secret revealed in the next call
+        #[ink(message)]
+        pub fn start_mtc(
+            &mut self,
+            deck_emo_base_ids: [u16; 6],
+            skin_ids: Vec<u16>,
+            seed_secret: [u8; 32],
+            next_seed_commitment: [u8; 32],
+        ) {
+            self.rollover_season_if_scheduled();
+
+            let player = self.get_player_by_caller();
+            let seed = self.reveal_seed(player, b"start_mtc", &seed_secret, next_seed_commitment);
+
+            let ep = self.create_or_update_player_ep(player);
+
+            let config = self.get_matchmaking_config();
+            let recent_opponents = self.get_player_recent_opponents(player);
+            let mut excluded_accounts = recent_opponents.clone();
+            excluded_accounts.push(player);
+
+            let ghosts = ghost::matchmake(
+                self,
+                ep,
+                seed,
+                &ghost::ChooseGhostsOptions {
+                    block_number: self.env().block_number(),
+                    excluded_accounts: &excluded_accounts,
+                    upward_band_search_len: config.upward_band_search_len,
+                    recency_half_life: config.recency_half_life,
+                },
+                self.emo_bases.as_ref().expect("emo_bases none"),
+                &[
+                    &self
+                        .deck_built_emo_base_ids
+                        .as_ref()
+                        .expect("deck_built_emo_base_ids none")[..],
+                    &self
+                        .deck_fixed_emo_base_ids
+                        .as_ref()
+                        .expect("deck_fixed_emo_base_ids none")[..],
+                ]
+                .concat(),
+            );
+
+            let ghost_accounts = ghosts.iter().map(|(a, _, _)| *a).collect::<Vec<_>>();
+            self.player_recent_opponents.insert(
+                player,
+                &ghost::update_recent_opponents(
+                    recent_opponents,
+                    &ghost_accounts,
+                    config.recent_opponents_mtc_len,
+                ),
+            );
+
+            self.assert_skin_selection(player, &deck_emo_base_ids, &skin_ids);
+            self.player_mtc_skins.insert(player, &skin_ids);
+
+            self.player_mtc_challenge.remove(player);
+            self.init_player_mtc(player, ep, seed, &deck_emo_base_ids, ghosts, false);
+        }
+
+        // the result of a challenge doesn't affect the player's ep
+        #[ink(message)]
+        pub fn start_mtc_challenge(
+            &mut self,
+            deck_emo_base_ids: [u16; 6],
+            ghost_sources: [GhostSource; 3],
+            seed_secret: [u8; 32],
+            next_seed_commitment: [u8; 32],
+        ) {
+            self.rollover_season_if_scheduled();
+
+            let player = self.get_player_by_caller();
+            let seed = self.reveal_seed(
+                player,
+                b"start_mtc_challenge",
+                &seed_secret,
+                next_seed_commitment,
+            );
+
+            let ep = self.create_or_update_player_ep(player);
+
+            let ghosts = ghost_sources
+                .into_iter()
+                .map(|source| self.get_ghost_by_source(source, ep))
+                .collect();
+
+            self.player_mtc_skins.remove(player);
+            self.player_mtc_challenge.insert(player, &());
+            self.init_player_mtc(player, ep, seed, &deck_emo_base_ids, ghosts, true);
+        }
+
+        #[ink(message)]
+        pub fn finish_mtc_shop(
+            &mut self,
+            player_operations: Vec<mtc::shop::PlayerOperation>,
+            seed_secret: [u8; 32],
+            next_seed_commitment: [u8; 32],
+        ) {
+            let player = self.get_player_by_caller();
+            let new_seed = self.reveal_seed(
+                player,
+                b"finish_mtc_shop",
+                &seed_secret,
+                next_seed_commitment,
+            );
+
+            let emo_bases = self.emo_bases.as_ref().expect("emo_bases none");
+
+            let (player_pool, player_ghosts) = self
+                .get_player_mtc_immutable(player)
+                .expect("player_mtc_immutable none");
+            let mut player_mtc_mutable = self
+                .player_mtc_mutable
+                .get(player)
+                .expect("player_mtc_mutable none");
+
+            let (turn, mut grade, mut board) =
+                get_turn_and_previous_grade_and_board(&player_mtc_mutable.grade_and_board_history);
+
+            board = shop::player_operation::verify_player_operations_and_update(
+                board,
+                &mut grade,
+                &mut player_mtc_mutable.upgrade_coin,
                 &player_operations,
                 &player_pool,
                 self.player_seed.get(player).expect("player_seed none"),
@@ -255,7 +890,10 @@ pub mod contract {
             )
             .expect("invalid shop player operations");
 
-            let new_seed = self.get_insecure_random_seed(player, b"finish_mtc_shop");
+            let ghosts = player_ghosts
+                .iter()
+                .map(|(a, ep, _)| (*a, *ep))
+                .collect::<Vec<_>>();
 
             let final_place = battle::organizer::battle_all(
                 &board,
@@ -264,7 +902,7 @@ pub mod contract {
                 grade,
                 &player_ghosts
                     .into_iter()
-                    .map(|o| o.map(|(_, g)| g).unwrap_or_default())
+                    .map(|(_, _, g)| g)
                     .collect::<Vec<_>>(),
                 player_mtc_mutable.battle_ghost_index,
                 turn,
@@ -280,10 +918,44 @@ pub mod contract {
                 new_seed,
                 player_mtc_mutable,
                 final_place,
+                ghosts,
             );
         }
     }
 
+    impl ghost::MatchmakingStorage<AccountId> for Contract {
+        // the ghosts registered before the ep was stored are rated by the owner's ep
+        fn get_ghosts_info(&self, ep_band: u16) -> Option<Vec<(BlockNumber, AccountId, u16)>> {
+            self.matchmaking_ghosts_info_v2.get(ep_band).or_else(|| {
+                self.matchmaking_ghosts_info.get(ep_band).map(|info| {
+                    info.into_iter()
+                        .map(|(block_number, account)| {
+                            let ep = self
+                                .player_ep
+                                .get(account)
+                                .unwrap_or(ep_band * ep::EP_BANDWIDTH);
+                            (block_number, account, ep)
+                        })
+                        .collect()
+                })
+            })
+        }
+
+        fn insert_ghosts_info(&mut self, ep_band: u16, info: Vec<(BlockNumber, AccountId, u16)>) {
+            self.matchmaking_ghosts_info_v2.insert(ep_band, &info);
+            self.matchmaking_ghosts_info.remove(ep_band);
+        }
+
+        fn get_ghost(&self, ep_band: u16, index: u8) -> Option<mtc::Ghost> {
+            self.matchmaking_ghost_by_index.get((ep_band, index))
+        }
+
+        fn insert_ghost(&mut self, ep_band: u16, index: u8, ghost: mtc::Ghost) {
+            self.matchmaking_ghost_by_index
+                .insert((ep_band, index), &ghost);
+        }
+    }
+
     #[ink(impl)]
     impl Contract {
         fn assert_admin(&self) -> AccountId {
@@ -295,6 +967,105 @@ pub mod contract {
             caller
         }
 
+        // at most one owned skin per emo base in the pool
+        fn assert_skin_selection(
+            &self,
+            player: AccountId,
+            deck_emo_base_ids: &[u16],
+            skin_ids: &[u16],
+        ) {
+            let deck_fixed_emo_base_ids = self
+                .deck_fixed_emo_base_ids
+                .as_ref()
+                .expect("deck_fixed_emo_base_ids none");
+            assert!(
+                skin_ids.len() <= deck_emo_base_ids.len() + deck_fixed_emo_base_ids.len(),
+                "start_mtc: too many skins"
+            );
+
+            let mut base_ids = Vec::with_capacity(skin_ids.len());
+            for &skin_id in skin_ids {
+                let skin = self.skins.get(skin_id).expect("start_mtc: skin not found");
+                assert!(
+                    self.player_skins.contains((player, skin_id)),
+                    "start_mtc: skin not owned"
+                );
+                assert!(
+                    deck_emo_base_ids.contains(&skin.base_id)
+                        || deck_fixed_emo_base_ids.contains(&skin.base_id),
+                    "start_mtc: skin base not in the pool"
+                );
+                assert!(
+                    !base_ids.contains(&skin.base_id),
+                    "start_mtc: duplicate skin base"
+                );
+                base_ids.push(skin.base_id);
+            }
+        }
+
+        // the caller itself unless it's a session
+        fn get_player_by_caller(&self) -> AccountId {
+            let caller = self.env().caller();
+
+            let player = match self.player_session_to_main.get(caller) {
+                Some(player) => player,
+                None => return caller,
+            };
+
+            let session = self
+                .player_main_to_session
+                .get(player)
+                .expect("player_main_to_session none");
+            assert!(
+                self.env().block_number() < session.expiry_block,
+                "session expired"
+            );
+
+            player
+        }
+
+        fn assert_session_available(&self, player: AccountId, session: AccountId) {
+            assert!(session != player, "session is the main account");
+            assert!(
+                !self.player_session_to_main.contains(player),
+                "main account is a session"
+            );
+            assert!(
+                !self.player_main_to_session.contains(session),
+                "session is a main account"
+            );
+            assert!(
+                self.player_session_to_main
+                    .get(session)
+                    .map_or(true, |main| main == player),
+                "session is used by another account"
+            );
+            // the storage would be unreachable while the session is set
+            assert!(
+                !self.player_ep.contains(session)
+                    && !self.player_seed_commitment.contains(session)
+                    && !self.player_mtc_mutable.contains(session),
+                "session has player storage"
+            );
+        }
+
+        fn remove_session(&mut self, player: AccountId) -> bool {
+            let session = match self.player_main_to_session.get(player) {
+                Some(session) => session,
+                None => return false,
+            };
+
+            self.player_main_to_session.remove(player);
+            self.player_session_to_main.remove(session.account);
+
+            self.env().emit_event(SessionRevoked {
+                player,
+                session: session.account,
+            });
+
+            true
+        }
+
         fn set_leaderboard(&mut self, x: Vec<(u16, AccountId)>) {
             self.lazy.insert(
                 LazyStorageKey::Leaderboard,
@@ -302,200 +1073,1640 @@ pub mod contract {
             );
         }
 
-        fn get_insecure_random_seed(&self, account_id: AccountId, subject: &[u8]) -> u64 {
+        fn set_season(&mut self, season: Season) {
+            self.lazy
+                .insert(LazyStorageKey::Season, &LazyStorageValue::Season(season));
+        }
+
+        fn start_next_season(&mut self) {
+            let season = self.get_season();
+            let block_number = self.env().block_number();
+
+            self.lazy.insert(
+                LazyStorageKey::PastSeason(season.id),
+                &LazyStorageValue::PastSeason(PastSeason {
+                    start_block: season.start_block,
+                    end_block: block_number,
+                    leaderboard: self.get_leaderboard(),
+                }),
+            );
+            self.set_leaderboard(vec![]);
+
+            let id = season.id.checked_add(1).expect("season id overflow");
+            self.set_season(Season {
+                id,
+                start_block: block_number,
+                duration: season.duration,
+            });
+
+            self.env().emit_event(SeasonStarted {
+                id,
+                start_block: block_number,
+            });
+        }
+
+        fn rollover_season_if_scheduled(&mut self) {
+            let season = self.get_season();
+            if let Some(duration) = season.duration {
+                if self.env().block_number() >= season.start_block.saturating_add(duration) {
+                    self.start_next_season();
+                }
+            }
+        }
+
+        fn set_player_ep(&mut self, player: AccountId, ep: u16) {
+            self.player_ep.insert(player, &ep);
+            self.player_ep_season.insert(player, &self.get_season().id);
+        }
+
+        fn get_random_pool(&self) -> ([u8; 32], BlockNumber) {
+            if let Some(LazyStorageValue::RandomPool(pool, block_number)) =
+                self.lazy.get(LazyStorageKey::RandomPool)
+            {
+                (pool, block_number)
+            } else {
+                Default::default()
+            }
+        }
+
+        // ink! doesn't expose block hashes, so the commitments and the secrets of all players are mixed in.
+        // the pool at the end of a block is kept until a later block updates it again
+        fn update_random_pool(&mut self, input: &[u8; 32]) {
+            let block_number = self.env().block_number();
+            let (pool, updated_block_number) = self.get_random_pool();
+
+            if updated_block_number < block_number {
+                self.random_pool_snapshots.insert(
+                    updated_block_number % SEED_REVEAL_PERIOD,
+                    &(updated_block_number, pool),
+                );
+            }
+
+            let pool = self.env().hash_encoded::<ink::env::hash::Blake2x256, _>(&(
+                pool,
+                input,
+                block_number,
+                self.env().block_timestamp(),
+            ));
+            self.lazy.insert(
+                LazyStorageKey::RandomPool,
+                &LazyStorageValue::RandomPool(pool, block_number),
+            );
+        }
+
+        // the pool at the end of a finished block, the pool is updated at from_block_number
+        fn get_random_pool_at(
+            &self,
+            from_block_number: BlockNumber,
+            block_number: BlockNumber,
+        ) -> [u8; 32] {
+            let (pool, updated_block_number) = self.get_random_pool();
+            if updated_block_number <= block_number {
+                return pool;
+            }
+
+            (from_block_number..=block_number)
+                .rev()
+                .find_map(|b| {
+                    self.random_pool_snapshots
+                        .get(b % SEED_REVEAL_PERIOD)
+                        .filter(|&(snapshot_block_number, _)| snapshot_block_number == b)
+                        .map(|(_, pool)| pool)
+                })
+                .expect("random_pool_snapshots none")
+        }
+
+        fn insert_seed_commitment(&mut self, player: AccountId, hash: [u8; 32]) {
+            self.update_random_pool(&hash);
+            self.player_seed_commitment.insert(
+                player,
+                &SeedCommitment {
+                    hash,
+                    block_number: self.env().block_number(),
+                },
+            );
+        }
+
+        // the seed depends only on the secret and the pool of a block after the commit,
+        // so revealing in another block, or reverting the reveal from a contract, doesn't reroll it
+        fn reveal_seed(
+            &mut self,
+            player: AccountId,
+            subject: &[u8],
+            seed_secret: &[u8; 32],
+            next_seed_commitment: [u8; 32],
+        ) -> u64 {
+            let commitment = self
+                .player_seed_commitment
+                .get(player)
+                .expect("player_seed_commitment none");
+
             assert!(
-                self.env().caller_is_origin(),
-                "contracts aren't supported yet"
+                self.env()
+                    .hash_bytes::<ink::env::hash::Blake2x256>(seed_secret)
+                    == commitment.hash,
+                "seed secret doesn't match the commitment"
             );
 
+            let block_number = self.env().block_number();
+            let entropy_block_number = commitment.block_number + SEED_ENTROPY_DELAY;
+            assert!(
+                block_number > entropy_block_number,
+                "seed revealed before the entropy block"
+            );
+            assert!(
+                block_number <= commitment.block_number.saturating_add(SEED_REVEAL_PERIOD),
+                "seed commitment expired"
+            );
+
+            let entropy = self.get_random_pool_at(commitment.block_number, entropy_block_number);
             let seed = self.env().hash_encoded::<ink::env::hash::Blake2x128, _>(&(
                 subject,
-                account_id,
-                self.env().block_timestamp(),
+                player,
+                seed_secret,
+                entropy,
             ));
 
+            self.update_random_pool(seed_secret);
+            self.insert_seed_commitment(player, next_seed_commitment);
+
             <u64>::decode(&mut seed.as_ref()).expect("failed to get seed")
         }
 
-        fn create_or_update_player_ep(&mut self, player: AccountId) -> u16 {
-            let new_ep = if let Some(old_ep) = self.player_ep.get(player) {
-                if self.player_mtc_mutable.contains(player) {
-                    ep::reduce_ep(old_ep, ep::EP_UNFINISH_PENALTY)
-                } else {
-                    return old_ep;
-                }
-            } else {
-                ep::INITIAL_EP
-            };
+        // the stored rating deviation and the rating periods since it was updated
+        fn get_player_rd_and_periods(&self, player: AccountId) -> (u16, u32) {
+            self.player_rd
+                .get(player)
+                .map_or((ep::MAX_RD, 0), |(rd, block_number)| {
+                    (
+                        rd,
+                        self.env().block_number().saturating_sub(block_number)
+                            / ep::RATING_PERIOD_BLOCKS,
+                    )
+                })
+        }
+
+        fn create_or_update_player_ep(&mut self, player: AccountId) -> u16 {
+            let new_ep = if let Some(old_ep) = self.get_player_ep(player) {
+                if self.player_mtc_mutable.contains(player)
+                    && !self.player_mtc_challenge.contains(player)
+                {
+                    ep::reduce_ep(old_ep, ep::EP_UNFINISH_PENALTY)
+                } else {
+                    old_ep
+                }
+            } else {
+                ep::INITIAL_EP
+            };
+
+            self.set_player_ep(player, new_ep);
+
+            new_ep
+        }
+
+        // the unfinished penalty is applied as if the mtc was abandoned
+        fn forfeit_mtc(&mut self, player: AccountId) {
+            self.create_or_update_player_ep(player);
+
+            self.player_mtc_immutable.remove(player);
+            self.player_mtc_immutable_v2.remove(player);
+            self.player_mtc_mutable.remove(player);
+            self.player_mtc_challenge.remove(player);
+            self.player_mtc_skins.remove(player);
+        }
+
+        // a ghost without the ep it was registered with shows the player's ep
+        fn get_ghost_by_source(
+            &self,
+            source: GhostSource,
+            ep: u16,
+        ) -> (Option<AccountId>, u16, mtc::Ghost) {
+            match source {
+                GhostSource::Matchmaking { ep_band, index } => {
+                    let (_, account, ghost_ep) =
+                        *ghost::MatchmakingStorage::get_ghosts_info(self, ep_band)
+                            .expect("matchmaking_ghosts_info none")
+                            .get(index as usize)
+                            .expect("invalid matchmaking ghost index");
+                    (
+                        Some(account),
+                        ghost_ep,
+                        self.matchmaking_ghost_by_index
+                            .get((ep_band, index))
+                            .expect("matchmaking_ghost_by_index none"),
+                    )
+                }
+                GhostSource::Account { ep_band, account } => {
+                    let (index, ghost_ep) =
+                        ghost::MatchmakingStorage::get_ghosts_info(self, ep_band)
+                            .expect("matchmaking_ghosts_info none")
+                            .iter()
+                            .enumerate()
+                            .find(|(_, (_, a, _))| a == &account)
+                            .map(|(i, (_, _, ghost_ep))| (i, *ghost_ep))
+                            .expect("matchmaking ghost not found for account");
+                    (
+                        Some(account),
+                        ghost_ep,
+                        self.matchmaking_ghost_by_index
+                            .get((ep_band, index as u8))
+                            .expect("matchmaking_ghost_by_index none"),
+                    )
+                }
+                GhostSource::Ghost { ghost } => {
+                    let emo_bases = self.emo_bases.as_ref().expect("emo_bases none");
+                    assert!(
+                        ghost
+                            .history
+                            .iter()
+                            .flat_map(|h| h.board.0.iter())
+                            .all(|e| emo_bases.find(e.base_id).is_ok()),
+                        "invalid ghost base id"
+                    );
+                    (None, ep, ghost)
+                }
+                GhostSource::Archive { account, seq } => {
+                    let len = self.get_player_ghost_archive_len(account);
+                    assert!(
+                        seq < len && len - seq <= GHOST_ARCHIVE_SIZE,
+                        "archived ghost not retained"
+                    );
+                    let archived = self
+                        .player_ghost_archive
+                        .get((account, seq % GHOST_ARCHIVE_SIZE))
+                        .expect("player_ghost_archive none");
+                    (Some(account), archived.ep, archived.ghost)
+                }
+            }
+        }
+
+        fn init_player_mtc(
+            &mut self,
+            player: AccountId,
+            ep: u16,
+            seed: u64,
+            deck_emo_base_ids: &[u16],
+            ghosts: Vec<(Option<AccountId>, u16, mtc::Ghost)>,
+            is_challenge: bool,
+        ) {
+            self.player_seed.insert(player, &seed);
+
+            let ghost_accounts = ghosts.iter().map(|(a, _, _)| *a).collect();
+
+            self.player_mtc_immutable.remove(player);
+            self.player_mtc_immutable_v2.insert(
+                player,
+                &(
+                    setup::build_pool(
+                        deck_emo_base_ids,
+                        self.emo_bases.as_ref().expect("emo_bases none"),
+                        self.deck_fixed_emo_base_ids
+                            .as_ref()
+                            .expect("deck_fixed_emo_base_ids none"),
+                        self.deck_built_emo_base_ids
+                            .as_ref()
+                            .expect("deck_built_emo_base_ids none"),
+                    )
+                    .expect("failed to build player pool"),
+                    ghosts,
+                ),
+            );
+
+            self.player_mtc_mutable.insert(
+                player,
+                &mtc::storage::PlayerMutable {
+                    health: setup::PLAYER_INITIAL_HEALTH,
+                    grade_and_board_history: Vec::new(),
+                    upgrade_coin: shop::coin::get_upgrade_coin(2),
+                    ghost_states: build_initial_ghost_states(ep),
+                    battle_ghost_index: 0,
+                },
+            );
+
+            self.env().emit_event(MtcStarted {
+                player,
+                ep,
+                is_challenge,
+                ghost_accounts,
+            });
+        }
+
+        fn update_for_finish_mtc_shop(
+            &mut self,
+            player: AccountId,
+            grade: u8,
+            board: mtc::Board,
+            new_seed: u64,
+            mut player_mtc_mutable: mtc::storage::PlayerMutable,
+            final_place: Option<u8>,
+            ghosts: Vec<(Option<AccountId>, u16)>,
+        ) {
+            player_mtc_mutable
+                .grade_and_board_history
+                .push(mtc::GradeAndBoard { grade, board });
+
+            self.env().emit_event(ShopFinished {
+                player,
+                turn: player_mtc_mutable.grade_and_board_history.len() as u8,
+                grade,
+                health: player_mtc_mutable.health,
+                ghost_states: player_mtc_mutable.ghost_states.clone(),
+            });
+
+            if let Some(place) = final_place {
+                self.finish_mtc(
+                    player,
+                    place,
+                    new_seed,
+                    ghosts,
+                    &player_mtc_mutable.ghost_states,
+                    &player_mtc_mutable.grade_and_board_history,
+                );
+            } else {
+                self.finish_mtc_turn(player, player_mtc_mutable, new_seed);
+            }
+
+            self.player_seed.insert(player, &new_seed);
+        }
+
+        fn finish_mtc(
+            &mut self,
+            player: AccountId,
+            place: u8,
+            seed: u64,
+            ghosts: Vec<(Option<AccountId>, u16)>, // (account, ep the ghost is rated by)
+            ghost_states: &[mtc::GhostState],
+            grade_and_board_history: &[mtc::GradeAndBoard],
+        ) {
+            self.player_mtc_immutable.remove(player);
+            self.player_mtc_immutable_v2.remove(player);
+            self.player_mtc_mutable.remove(player);
+            self.player_mtc_skins.remove(player);
+
+            if self.player_ghost_archive_enabled.contains(player) {
+                self.archive_ghost(player, place, grade_and_board_history);
+            }
+
+            let old_ep = self.get_player_ep(player).expect("player_ep none");
+
+            let is_challenge = self.player_mtc_challenge.contains(player);
+            let new_ep = if is_challenge {
+                self.player_mtc_challenge.remove(player);
+                old_ep
+            } else {
+                self.update_ep_and_matchmaking_ghost(
+                    player,
+                    place,
+                    old_ep,
+                    &ghosts,
+                    ghost_states,
+                    grade_and_board_history,
+                )
+            };
+
+            let mtc::GradeAndBoard { grade, board } = grade_and_board_history
+                .last()
+                .cloned()
+                .expect("grade_and_board_history empty");
+
+            let result = MatchResult {
+                place,
+                old_ep,
+                new_ep,
+                grade,
+                board,
+                turn: grade_and_board_history.len() as u8,
+                ghost_accounts: ghosts.into_iter().map(|(a, _)| a).collect(),
+                seed,
+            };
+
+            if !is_challenge {
+                self.update_player_stats(player, &result);
+            }
+
+            self.player_match_result.insert(player, &result);
+            self.env().emit_event(MtcFinished { player, result });
+
+            if !is_challenge {
+                self.update_player_achievements(player, place, grade_and_board_history);
+            }
+        }
+
+        // the battle logs are only built with the view-logs feature,
+        // so the achievements on them are not earnable in the contract
+        fn update_player_achievements(
+            &mut self,
+            player: AccountId,
+            place: u8,
+            grade_and_board_history: &[mtc::GradeAndBoard],
+        ) {
+            let mut earned = self.get_player_achievements(player);
+
+            let new = achievement::evaluate_achievements(
+                &achievement::Input {
+                    place,
+                    grade_and_board_history,
+                    battle_logs: None,
+                    emo_bases: self.emo_bases.as_ref().expect("emo_bases none"),
+                },
+                &earned,
+            );
+
+            if new.is_empty() {
+                return;
+            }
+
+            earned.extend(new.iter().copied());
+            self.player_achievements.insert(player, &earned);
+            self.env()
+                .emit_event(AchievementsEarned { player, ids: new });
+        }
+
+        fn update_player_stats(&mut self, player: AccountId, result: &MatchResult) {
+            let mut stats = self.player_stats.get(player).unwrap_or_default();
+
+            stats.played_count = stats.played_count.saturating_add(1);
+            let place_count = &mut stats.place_counts[result.place as usize - 1];
+            *place_count = place_count.saturating_add(1);
+            stats.highest_ep = stats.highest_ep.max(result.new_ep);
+            stats.emo_counts = update_emo_counts(stats.emo_counts, &result.board);
+
+            if stats.best_result.as_ref().map_or(true, |best| {
+                is_better_result(result.place, result.grade, best.place, best.grade)
+            }) {
+                stats.best_result = Some(result.clone());
+            }
+
+            if result.place == 1 {
+                stats.win_streak = stats.win_streak.saturating_add(1);
+                stats.max_win_streak = stats.max_win_streak.max(stats.win_streak);
+            } else {
+                stats.win_streak = 0;
+            }
+
+            self.player_stats.insert(player, &stats);
+        }
+
+        fn update_ep_and_matchmaking_ghost(
+            &mut self,
+            player: AccountId,
+            place: u8,
+            old_ep: u16,
+            ghosts: &[(Option<AccountId>, u16)],
+            ghost_states: &[mtc::GhostState],
+            grade_and_board_history: &[mtc::GradeAndBoard],
+        ) -> u16 {
+            let ghost_ratings = ghost::get_ghost_ratings(ghosts, |a| self.get_player_rd(*a));
+
+            let (rd, periods) = self.get_player_rd_and_periods(player);
+            let new_rating = ep::calculate_new_rating(
+                ep::Rating { ep: old_ep, rd },
+                place,
+                &ep::sort_ghost_ratings(ghost_states, &ghost_ratings),
+                periods,
+            );
+            let new_ep = new_rating.ep;
+
+            self.player_rd
+                .insert(player, &(new_rating.rd, self.env().block_number()));
+
+            if let Some(leaderboard) = update_leaderboard(self.get_leaderboard(), new_ep, &player) {
+                self.set_leaderboard(leaderboard);
+            }
+
+            self.set_player_ep(player, new_ep);
+
+            let block_number = self.env().block_number();
+            ghost::register_finished_mtc_ghost(
+                self,
+                player,
+                place,
+                new_ep,
+                block_number,
+                grade_and_board_history,
+            );
+
+            new_ep
+        }
+
+        fn archive_ghost(
+            &mut self,
+            player: AccountId,
+            place: u8,
+            grade_and_board_history: &[mtc::GradeAndBoard],
+        ) {
+            let len = self.get_player_ghost_archive_len(player);
+
+            self.player_ghost_archive.insert(
+                (player, len % GHOST_ARCHIVE_SIZE),
+                &ArchivedGhost {
+                    ghost: ghost::build_ghost_from_history(grade_and_board_history),
+                    ep: self.get_player_ep(player).expect("player_ep none"),
+                    place,
+                    block_number: self.env().block_number(),
+                },
+            );
+            self.player_ghost_archive_len
+                .insert(player, &len.saturating_add(1));
+        }
+
+        fn finish_mtc_turn(
+            &mut self,
+            player: AccountId,
+            mut player_mtc_mutable: mtc::storage::PlayerMutable,
+            new_seed: u64,
+        ) {
+            update_player_mtc_mutable_after_battle(&mut player_mtc_mutable, new_seed);
+            self.player_mtc_mutable.insert(player, &player_mtc_mutable);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        type Event = <Contract as ink::reflect::ContractEventBase>::Type;
+
+        fn get_recorded_events() -> Vec<Event> {
+            ink::env::test::recorded_events()
+                .map(|e| <Event as Decode>::decode(&mut &e.data[..]).expect("invalid event"))
+                .collect()
+        }
+
+        fn set_caller(caller: AccountId) {
+            ink::env::test::set_caller::<Environment>(caller);
+        }
+
+        fn get_default_accounts() -> ink::env::test::DefaultAccounts<Environment> {
+            ink::env::test::default_accounts::<Environment>()
+        }
+
+        fn get_account(n: u8) -> AccountId {
+            assert!(n < 255, "not supported");
+            let mut arr = [0xff; 32];
+            arr[0] -= n + 1;
+            AccountId::from(arr)
+        }
+
+        struct AccountIdForDebug(AccountId);
+        impl std::fmt::Debug for AccountIdForDebug {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let a: &[u8; 32] = self.0.as_ref();
+                write!(f, "a:{}", 254 - a[0])
+            }
+        }
+
+        fn get_current_block_number() -> BlockNumber {
+            ink::env::block_number::<Environment>()
+        }
+
+        fn advance_block() -> BlockNumber {
+            ink::env::test::advance_block::<Environment>();
+            get_current_block_number()
+        }
+
+        // to the first block the last committed secret can be revealed in
+        fn advance_to_seed_reveal() -> BlockNumber {
+            for _ in 0..=SEED_ENTROPY_DELAY {
+                advance_block();
+            }
+            get_current_block_number()
+        }
+
+        fn init_contract() -> Contract {
+            set_caller(get_default_accounts().alice);
+            Contract::new()
+        }
+
+        // finishing an mtc needs the emo bases
+        fn init_contract_with_emo_bases() -> Contract {
+            let mut contract = init_contract();
+            contract.emo_bases = Some(emo::Bases::new());
+            contract
+        }
+
+        fn register_ghost(
+            contract: &mut Contract,
+            player: AccountId,
+            ep: u16,
+            grade_and_board_history: &[mtc::GradeAndBoard],
+        ) {
+            let block_number = get_current_block_number();
+            ghost::register_matchmaking_ghost(
+                contract,
+                player,
+                ep,
+                block_number,
+                grade_and_board_history,
+            );
+        }
+
+        #[ink::test]
+        fn new() {
+            assert_eq!(init_contract().admins, vec![get_default_accounts().alice]);
+        }
+
+        #[ink::test]
+        fn add_and_remove_admin() {
+            let mut contract = init_contract();
+            let account = get_account(0);
+
+            contract.add_admin(account);
+            assert_eq!(
+                contract.get_admins(),
+                vec![get_default_accounts().alice, account]
+            );
+            contract.remove_admin(account);
+            assert_eq!(contract.get_admins(), vec![get_default_accounts().alice]);
+
+            let events = get_recorded_events();
+            assert_eq!(events.len(), 2);
+            assert!(
+                matches!(&events[0], Event::AdminAdded(AdminAdded { account: a }) if a == &account)
+            );
+            assert!(
+                matches!(&events[1], Event::AdminRemoved(AdminRemoved { account: a }) if a == &account)
+            );
+        }
+
+        #[ink::test]
+        fn create_or_update_player_ep() {
+            let mut contract = init_contract();
+
+            let account0 = get_account(0);
+            assert_eq!(contract.player_ep.get(account0), None);
+            assert_eq!(contract.create_or_update_player_ep(account0), 300);
+            assert_eq!(contract.player_ep.get(account0), Some(300));
+
+            let account1 = get_account(1);
+            contract.player_ep.insert(account1, &400);
+            assert_eq!(contract.create_or_update_player_ep(account1), 400);
+            assert_eq!(contract.player_ep.get(account1), Some(400));
+
+            let account2 = get_account(2);
+            contract.player_ep.insert(account2, &500);
+            let m: mtc::storage::PlayerMutable = Default::default();
+            contract.player_mtc_mutable.insert(account2, &m);
+            assert_eq!(contract.create_or_update_player_ep(account2), 440);
+            assert_eq!(contract.player_ep.get(account2), Some(440));
+
+            let account3 = get_account(3);
+            contract.player_ep.insert(account3, &500);
+            contract.player_mtc_mutable.insert(account3, &m);
+            contract.player_mtc_challenge.insert(account3, &());
+            assert_eq!(contract.create_or_update_player_ep(account3), 500);
+            assert_eq!(contract.player_ep.get(account3), Some(500));
+        }
+
+        fn build_seed_commitment(seed_secret: &[u8; 32]) -> [u8; 32] {
+            let mut output = [0u8; 32];
+            ink::env::hash_bytes::<ink::env::hash::Blake2x256>(seed_secret, &mut output);
+            output
+        }
+
+        fn build_seed(
+            subject: &[u8],
+            player: AccountId,
+            seed_secret: &[u8; 32],
+            pool: [u8; 32],
+        ) -> u64 {
+            let mut output = [0u8; 16];
+            ink::env::hash_encoded::<ink::env::hash::Blake2x128, _>(
+                &(subject, player, seed_secret, pool),
+                &mut output,
+            );
+            <u64>::decode(&mut output.as_ref()).unwrap()
+        }
+
+        #[ink::test]
+        fn reveal_seed() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+            let seed_secret = [7u8; 32];
+
+            set_caller(player);
+            contract.commit_seed(build_seed_commitment(&seed_secret));
+            let commitment = contract.get_player_seed_commitment(player).unwrap();
+            let (pool, _) = contract.get_random_pool();
+
+            advance_to_seed_reveal();
+            let seed = contract.reveal_seed(player, b"start_mtc", &seed_secret, [1u8; 32]);
+            assert_eq!(seed, build_seed(b"start_mtc", player, &seed_secret, pool));
+            assert_eq!(
+                contract.get_player_seed_commitment(player).unwrap().hash,
+                [1u8; 32]
+            );
+            assert_ne!(contract.get_random_pool().0, pool);
+
+            contract.player_seed_commitment.insert(player, &commitment);
+            assert_ne!(
+                contract.reveal_seed(player, b"finish_mtc_shop", &seed_secret, [1u8; 32]),
+                seed
+            );
+        }
+
+        // the pool at the end of the entropy block is unknown when the secret is committed
+        #[ink::test]
+        fn reveal_seed_unknown_at_commit() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+            let seed_secret = [7u8; 32];
+
+            set_caller(player);
+            contract.commit_seed(build_seed_commitment(&seed_secret));
+            let (pool_at_commit, _) = contract.get_random_pool();
+
+            advance_block();
+            set_caller(get_account(1));
+            contract.commit_seed(build_seed_commitment(&[8u8; 32]));
+            let (pool, _) = contract.get_random_pool();
+
+            advance_block();
+            set_caller(player);
+            let seed = contract.reveal_seed(player, b"start_mtc", &seed_secret, [1u8; 32]);
+            assert_ne!(
+                seed,
+                build_seed(b"start_mtc", player, &seed_secret, pool_at_commit)
+            );
+            assert_eq!(seed, build_seed(b"start_mtc", player, &seed_secret, pool));
+        }
+
+        // the seed can't be chosen by picking the reveal block, whatever happens after the entropy block
+        #[ink::test]
+        fn reveal_seed_in_any_block() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+            let seed_secret = [7u8; 32];
+
+            set_caller(player);
+            contract.commit_seed(build_seed_commitment(&seed_secret));
+            let commitment = contract.get_player_seed_commitment(player).unwrap();
+
+            advance_to_seed_reveal();
+            let seed = contract.reveal_seed(player, b"start_mtc", &seed_secret, [1u8; 32]);
+
+            for i in 0..10u8 {
+                advance_block();
+                set_caller(get_account(1));
+                contract.commit_seed(build_seed_commitment(&[i; 32]));
+
+                set_caller(player);
+                contract.player_seed_commitment.insert(player, &commitment);
+                assert_eq!(
+                    contract.reveal_seed(player, b"start_mtc", &seed_secret, [1u8; 32]),
+                    seed
+                );
+            }
+        }
+
+        // a contract caller reverting the reveal gets the same seed on the retry
+        #[ink::test]
+        fn reveal_seed_by_contract() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+            let seed_secret = [7u8; 32];
+            ink::env::test::set_contract::<Environment>(player);
+
+            set_caller(player);
+            contract.commit_seed(build_seed_commitment(&seed_secret));
+            let commitment = contract.get_player_seed_commitment(player).unwrap();
+            let (pool, _) = contract.get_random_pool();
+
+            advance_to_seed_reveal();
+            let seed = contract.reveal_seed(player, b"start_mtc", &seed_secret, [1u8; 32]);
+            assert_eq!(seed, build_seed(b"start_mtc", player, &seed_secret, pool));
+
+            contract.player_seed_commitment.insert(player, &commitment);
+            advance_block();
+            assert_eq!(
+                contract.reveal_seed(player, b"start_mtc", &seed_secret, [1u8; 32]),
+                seed
+            );
+        }
+
+        #[ink::test]
+        fn get_random_pool_at() {
+            let mut contract = init_contract();
+
+            let start = get_current_block_number();
+            contract.update_random_pool(&[1u8; 32]);
+            let (pool1, _) = contract.get_random_pool();
+            contract.update_random_pool(&[2u8; 32]);
+            let (pool2, _) = contract.get_random_pool();
+            advance_block();
+            advance_block();
+            contract.update_random_pool(&[3u8; 32]);
+            let (pool3, _) = contract.get_random_pool();
+
+            assert_ne!(pool1, pool2);
+            assert_eq!(contract.get_random_pool_at(start, start), pool2);
+            assert_eq!(contract.get_random_pool_at(start, start + 1), pool2);
+            assert_eq!(contract.get_random_pool_at(start, start + 2), pool3);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "seed revealed before the entropy block")]
+        fn reveal_seed_in_entropy_block() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+
+            set_caller(player);
+            contract.commit_seed(build_seed_commitment(&[7u8; 32]));
+            for _ in 0..SEED_ENTROPY_DELAY {
+                advance_block();
+            }
+            contract.reveal_seed(player, b"start_mtc", &[7u8; 32], [0u8; 32]);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "seed commitment expired")]
+        fn reveal_seed_expired() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+
+            set_caller(player);
+            contract.commit_seed(build_seed_commitment(&[7u8; 32]));
+            for _ in 0..=SEED_REVEAL_PERIOD {
+                advance_block();
+            }
+            contract.reveal_seed(player, b"start_mtc", &[7u8; 32], [0u8; 32]);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "seed secret doesn't match the commitment")]
+        fn reveal_seed_mismatch() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+
+            set_caller(player);
+            contract.commit_seed(build_seed_commitment(&[7u8; 32]));
+            contract.reveal_seed(player, b"start_mtc", &[8u8; 32], [0u8; 32]);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "player_seed_commitment none")]
+        fn reveal_seed_without_commitment() {
+            let mut contract = init_contract();
+            contract.reveal_seed(get_account(0), b"start_mtc", &[7u8; 32], [0u8; 32]);
+        }
+
+        // the mtcs started before the upgrade are read at the key of the old layout
+        #[ink::test]
+        fn get_player_mtc_immutable_legacy() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+            let key =
+                ink::primitives::KeyComposer::compute_key("Contract", "", "player_mtc_immutable")
+                    .unwrap();
+            let ghost = mtc::Ghost {
+                history: vec![Default::default()],
+            };
+            let legacy: LegacyPlayerImmutable =
+                (vec![], vec![Some((get_account(1), ghost.clone())), None]);
+            ink::env::set_contract_storage(&(key, player), &legacy);
+
+            assert_eq!(
+                contract.get_player_mtc_immutable(player),
+                Some((
+                    vec![],
+                    vec![
+                        (Some(get_account(1)), ep::INITIAL_EP, ghost),
+                        (None, ep::INITIAL_EP, Default::default())
+                    ]
+                ))
+            );
+
+            contract.player_ep.insert(player, &300);
+            contract.forfeit_mtc(player);
+            assert_eq!(contract.get_player_mtc_immutable(player), None);
+        }
+
+        #[ink::test]
+        fn commit_seed_in_mtc() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+
+            set_caller(player);
+            let m: mtc::storage::PlayerMutable = Default::default();
+            contract.player_ep.insert(player, &500);
+            contract.player_mtc_mutable.insert(player, &m);
+            contract.player_mtc_skins.insert(player, &vec![1]);
+            contract.commit_seed([1u8; 32]);
+
+            assert_eq!(contract.get_player_ep(player), Some(440));
+            assert_eq!(contract.get_player_mtc_mutable(player), None);
+            assert_eq!(contract.get_player_mtc_skins(player), Vec::<u16>::new());
+            assert_eq!(
+                contract.get_player_seed_commitment(player).unwrap().hash,
+                [1u8; 32]
+            );
+
+            // a challenge is forfeited without the penalty
+            contract.player_mtc_mutable.insert(player, &m);
+            contract.player_mtc_challenge.insert(player, &());
+            contract.commit_seed([2u8; 32]);
+            assert_eq!(contract.get_player_ep(player), Some(440));
+            assert!(!contract.get_player_mtc_challenge(player));
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "seed secret doesn't match the commitment")]
+        fn finish_mtc_shop_reveal_mismatch() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+
+            set_caller(player);
+            contract.commit_seed(build_seed_commitment(&[7u8; 32]));
+            contract.finish_mtc_shop(vec![], [8u8; 32], [0u8; 32]);
+        }
+
+        #[ink::test]
+        fn set_matchmaking_config() {
+            let mut contract = init_contract();
+            assert_eq!(
+                contract.get_matchmaking_config(),
+                MatchmakingConfig::default()
+            );
+
+            let config = MatchmakingConfig {
+                recent_opponents_mtc_len: 1,
+                upward_band_search_len: 0,
+                recency_half_life: 10,
+            };
+            contract.set_matchmaking_config(config.clone());
+            assert_eq!(contract.get_matchmaking_config(), config);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "set_matchmaking_config: recent_opponents_mtc_len too large")]
+        fn set_matchmaking_config_too_large() {
+            let mut contract = init_contract();
+            contract.set_matchmaking_config(MatchmakingConfig {
+                recent_opponents_mtc_len: MAX_RECENT_OPPONENTS_MTC_LEN + 1,
+                ..Default::default()
+            });
+        }
+
+        #[ink::test]
+        fn session() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+            let session = get_account(1);
+
+            set_caller(player);
+            contract.set_session(session, 2);
+            assert_eq!(contract.get_player_main(session), None);
+            assert_eq!(
+                contract.get_player_session_offer(session),
+                Some((player, 2))
+            );
+
+            set_caller(session);
+            contract.accept_session(player);
+            assert_eq!(contract.get_player_main(session), Some(player));
+            assert_eq!(contract.get_player_session_offer(session), None);
+
+            set_caller(session);
+            assert_eq!(contract.get_player_by_caller(), player);
+            contract.commit_seed([1u8; 32]);
+            assert_eq!(
+                contract.get_player_seed_commitment(player).unwrap().hash,
+                [1u8; 32]
+            );
+            assert_eq!(contract.get_player_seed_commitment(session), None);
+
+            set_caller(player);
+            assert_eq!(contract.get_player_by_caller(), player);
+            contract.set_session(get_account(2), 2);
+            set_caller(get_account(2));
+            contract.accept_session(player);
+            assert_eq!(contract.get_player_main(session), None);
+            assert_eq!(contract.get_player_main(get_account(2)), Some(player));
+
+            set_caller(session);
+            assert_eq!(contract.get_player_by_caller(), session);
+
+            set_caller(get_account(2));
+            contract.revoke_session();
+            assert_eq!(contract.get_player_session(player), None);
+            assert_eq!(contract.get_player_main(get_account(2)), None);
+
+            let events = get_recorded_events();
+            assert!(matches!(
+                events.last(),
+                Some(Event::SessionRevoked(SessionRevoked { player: p, session: s }))
+                    if *p == player && *s == get_account(2)
+            ));
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "session expired")]
+        fn session_expired() {
+            let mut contract = init_contract();
+            let session = get_account(1);
+
+            set_caller(get_account(0));
+            contract.set_session(session, 2);
+            set_caller(session);
+            contract.accept_session(get_account(0));
+
+            advance_block();
+            advance_block();
+            set_caller(session);
+            contract.commit_seed([1u8; 32]);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "session is used by another account")]
+        fn set_session_used() {
+            let mut contract = init_contract();
+            let session = get_account(2);
+
+            set_caller(get_account(0));
+            contract.set_session(session, 2);
+            set_caller(session);
+            contract.accept_session(get_account(0));
+            set_caller(get_account(1));
+            contract.set_session(session, 2);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "accept_session: session is offered by another account")]
+        fn accept_session_other_account() {
+            let mut contract = init_contract();
+            let session = get_account(2);
+
+            set_caller(get_account(0));
+            contract.set_session(session, 2);
+            set_caller(session);
+            contract.accept_session(get_account(1));
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "session has player storage")]
+        fn set_session_player_storage() {
+            let mut contract = init_contract();
+            let session = get_account(1);
+
+            set_caller(session);
+            contract.commit_seed([1u8; 32]);
+            set_caller(get_account(0));
+            contract.set_session(session, 2);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "main account is a session")]
+        fn set_session_by_session() {
+            let mut contract = init_contract();
+            let session = get_account(1);
+
+            set_caller(get_account(0));
+            contract.set_session(session, 2);
+            set_caller(session);
+            contract.accept_session(get_account(0));
+            contract.set_session(get_account(2), 2);
+        }
+
+        #[ink::test]
+        fn rollover_season() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+
+            contract.set_player_ep(player, 500);
+            contract.set_leaderboard(vec![(500, player)]);
+            assert_eq!(contract.get_season(), Default::default());
+
+            advance_block();
+            contract.rollover_season();
+
+            assert_eq!(
+                contract.get_season(),
+                Season {
+                    id: 1,
+                    start_block: 1,
+                    duration: None
+                }
+            );
+            assert_eq!(
+                contract.get_past_season(0),
+                Some(PastSeason {
+                    start_block: 0,
+                    end_block: 1,
+                    leaderboard: vec![(500, player)]
+                })
+            );
+            assert_eq!(contract.get_past_season(1), None);
+            assert_eq!(contract.get_leaderboard(), vec![]);
+
+            assert_eq!(contract.player_ep.get(player), Some(500));
+            assert_eq!(contract.get_player_ep(player), Some(400));
+            assert_eq!(contract.create_or_update_player_ep(player), 400);
+            assert_eq!(contract.player_ep.get(player), Some(400));
+            assert_eq!(contract.player_ep_season.get(player), Some(1));
+
+            contract.rollover_season();
+            contract.rollover_season();
+            assert_eq!(contract.get_player_ep(player), Some(325));
+
+            assert!(matches!(
+                get_recorded_events().last(),
+                Some(Event::SeasonStarted(SeasonStarted { id: 3, .. }))
+            ));
+        }
+
+        #[ink::test]
+        fn rollover_season_if_scheduled() {
+            let mut contract = init_contract();
+
+            contract.rollover_season_if_scheduled();
+            assert_eq!(contract.get_season().id, 0);
+
+            contract.set_season_duration(Some(2));
+            advance_block();
+            contract.rollover_season_if_scheduled();
+            assert_eq!(contract.get_season().id, 0);
+
+            advance_block();
+            contract.rollover_season_if_scheduled();
+            assert_eq!(
+                contract.get_season(),
+                Season {
+                    id: 1,
+                    start_block: 2,
+                    duration: Some(2)
+                }
+            );
+            assert_eq!(contract.get_past_season(0).unwrap().end_block, 2);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "assert_admin: caller is not admin")]
+        fn rollover_season_not_admin() {
+            let mut contract = init_contract();
+            set_caller(get_account(0));
+            contract.rollover_season();
+        }
+
+        #[ink::test]
+        fn get_ghost_by_source() {
+            let mut contract = init_contract();
+            let ep_band = ep::get_ep_band(300);
+
+            let history = vec![mtc::GradeAndBoard {
+                grade: 2,
+                board: Default::default(),
+            }];
+            register_ghost(&mut contract, get_account(0), 300, &history);
+            register_ghost(&mut contract, get_account(1), 320, &history);
+            let ghost = ghost::build_ghost_from_history(&history);
+
+            // a matchmaking ghost keeps the ep it was registered with
+            assert_eq!(
+                contract.get_ghost_by_source(GhostSource::Matchmaking { ep_band, index: 1 }, 310),
+                (Some(get_account(1)), 320, ghost.clone())
+            );
+            assert_eq!(
+                contract.get_ghost_by_source(
+                    GhostSource::Account {
+                        ep_band,
+                        account: get_account(0)
+                    },
+                    310
+                ),
+                (Some(get_account(0)), 300, ghost)
+            );
+        }
+
+        #[ink::test]
+        fn get_ghost_by_source_ghost() {
+            let contract = init_contract_with_skins();
+            let ghost = build_ghost_with_base_id(1);
+
+            assert_eq!(
+                contract.get_ghost_by_source(
+                    GhostSource::Ghost {
+                        ghost: ghost.clone()
+                    },
+                    310
+                ),
+                (None, 310, ghost)
+            );
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "invalid ghost base id")]
+        fn get_ghost_by_source_ghost_invalid_base_id() {
+            let contract = init_contract_with_skins();
+            contract.get_ghost_by_source(
+                GhostSource::Ghost {
+                    ghost: build_ghost_with_base_id(4),
+                },
+                300,
+            );
+        }
+
+        fn build_ghost_with_base_id(base_id: u16) -> mtc::Ghost {
+            mtc::Ghost {
+                history: vec![mtc::GradeAndGhostBoard {
+                    grade: 1,
+                    board: mtc::GhostBoard(vec![mtc::GhostBoardEmo {
+                        base_id,
+                        attributes: Default::default(),
+                    }]),
+                }],
+            }
+        }
 
-            self.player_ep.insert(player, &new_ep);
+        #[ink::test]
+        #[should_panic(expected = "matchmaking ghost not found for account")]
+        fn get_ghost_by_source_unknown_account() {
+            let mut contract = init_contract();
 
-            new_ep
+            register_ghost(&mut contract, get_account(0), 300, &[Default::default()]);
+            contract.get_ghost_by_source(
+                GhostSource::Account {
+                    ep_band: ep::get_ep_band(300),
+                    account: get_account(1),
+                },
+                300,
+            );
         }
 
-        fn update_for_finish_mtc_shop(
-            &mut self,
-            player: AccountId,
-            grade: u8,
-            board: mtc::Board,
-            new_seed: u64,
-            mut player_mtc_mutable: mtc::storage::PlayerMutable,
-            final_place: Option<u8>,
-        ) {
-            player_mtc_mutable
-                .grade_and_board_history
-                .push(mtc::GradeAndBoard { grade, board });
+        #[ink::test]
+        fn archive_ghost() {
+            let mut contract = init_contract_with_emo_bases();
+            let player = get_account(0);
 
-            if let Some(place) = final_place {
-                self.finish_mtc(player, place, &player_mtc_mutable.grade_and_board_history);
-            } else {
-                self.finish_mtc_turn(player, player_mtc_mutable, new_seed);
+            fn build_grade_and_board_vec(grade: u8) -> Vec<mtc::GradeAndBoard> {
+                vec![mtc::GradeAndBoard {
+                    grade,
+                    board: Default::default(),
+                }]
             }
 
-            self.player_seed.insert(player, &new_seed);
-        }
+            contract.player_ep.insert(player, &300);
+            contract.finish_mtc(player, 1, 0, vec![], &[], &build_grade_and_board_vec(1));
+            assert_eq!(contract.get_player_ghost_archive_len(player), 0);
+            assert_eq!(contract.get_player_ghost_archive(player, 0), vec![]);
 
-        fn finish_mtc(
-            &mut self,
-            player: AccountId,
-            place: u8,
-            grade_and_board_history: &[mtc::GradeAndBoard],
-        ) {
-            let old_ep = self.player_ep.get(player).expect("player_ep none");
-            let new_ep = calc_new_ep(place, old_ep);
+            set_caller(player);
+            contract.set_ghost_archive_enabled(true);
+            assert!(contract.get_player_ghost_archive_enabled(player));
 
-            if let Some(leaderboard) = update_leaderboard(self.get_leaderboard(), new_ep, &player) {
-                self.set_leaderboard(leaderboard);
+            for grade in 1..=(GHOST_ARCHIVE_SIZE as u8 + 2) {
+                contract.finish_mtc(player, 2, 0, vec![], &[], &build_grade_and_board_vec(grade));
             }
 
-            self.player_ep.insert(player, &new_ep);
+            let len = GHOST_ARCHIVE_SIZE + 2;
+            assert_eq!(contract.get_player_ghost_archive_len(player), len);
+
+            let page = contract.get_player_ghost_archive(player, 0);
+            assert_eq!(page.len(), GHOST_ARCHIVE_PAGE_SIZE as usize);
+            assert_eq!(page[0].0, len - 1);
+            assert_eq!(page[0].1.place, 2);
+            assert_eq!(page[0].1.block_number, get_current_block_number());
+            assert_eq!(
+                page[0].1.ghost,
+                ghost::build_ghost_from_history(&build_grade_and_board_vec(len as u8))
+            );
+            assert_eq!(page[1].0, len - 2);
+
+            let last_page = contract
+                .get_player_ghost_archive(player, GHOST_ARCHIVE_SIZE / GHOST_ARCHIVE_PAGE_SIZE - 1);
+            assert_eq!(last_page.last().unwrap().0, 2);
+            assert_eq!(
+                contract
+                    .get_player_ghost_archive(player, GHOST_ARCHIVE_SIZE / GHOST_ARCHIVE_PAGE_SIZE),
+                vec![]
+            );
+
+            let archived_ep = contract
+                .player_ghost_archive
+                .get((player, 2 % GHOST_ARCHIVE_SIZE))
+                .unwrap()
+                .ep;
+            assert_eq!(
+                contract.get_ghost_by_source(
+                    GhostSource::Archive {
+                        account: player,
+                        seq: 2
+                    },
+                    300
+                ),
+                (
+                    Some(player),
+                    archived_ep,
+                    ghost::build_ghost_from_history(&build_grade_and_board_vec(3))
+                )
+            );
+
+            contract.set_ghost_archive_enabled(false);
+            contract.finish_mtc(player, 3, 0, vec![], &[], &build_grade_and_board_vec(1));
+            assert_eq!(contract.get_player_ghost_archive_len(player), len);
+        }
 
-            if !grade_and_board_history.last().unwrap().board.0.is_empty() {
-                self.add_matchmaking_ghost(player, old_ep, grade_and_board_history);
+        #[ink::test]
+        #[should_panic(expected = "archived ghost not retained")]
+        fn get_ghost_by_source_overwritten_archive() {
+            let mut contract = init_contract_with_emo_bases();
+            let player = get_account(0);
+
+            contract.player_ep.insert(player, &300);
+            set_caller(player);
+            contract.set_ghost_archive_enabled(true);
+            for _ in 0..=GHOST_ARCHIVE_SIZE {
+                contract.finish_mtc(player, 1, 0, vec![], &[], &[Default::default()]);
             }
 
-            self.player_mtc_immutable.remove(player);
-            self.player_mtc_mutable.remove(player);
+            contract.get_ghost_by_source(
+                GhostSource::Archive {
+                    account: player,
+                    seq: 0,
+                },
+                300,
+            );
         }
 
-        fn add_matchmaking_ghost(
-            &mut self,
-            player: AccountId,
-            ep: u16,
-            grade_and_board_history: &[mtc::GradeAndBoard],
-        ) {
-            let ep_band = ep::get_ep_band(ep);
-            let player_info_element = (self.env().block_number(), player);
-
-            let (info, index) = if let Some(mut info) = self.matchmaking_ghosts_info.get(ep_band) {
-                let index = if let Some(idx) = info.iter().position(|(_, a)| a == &player) {
-                    info[idx] = player_info_element;
-                    idx
-                } else if info.len() < 20 {
-                    info.push(player_info_element);
-                    info.len() - 1
-                } else {
-                    let mut iter = info.iter().enumerate();
-                    let (mut oldest_idx, (mut oldest_num, _)) = iter.next().unwrap();
-                    for (idx, &(num, _)) in iter {
-                        if num < oldest_num {
-                            oldest_num = num;
-                            oldest_idx = idx;
-                        }
-                    }
-                    info[oldest_idx] = player_info_element;
-                    oldest_idx
-                };
+        #[ink::test]
+        fn update_for_finish_mtc_shop() {
+            let mut contract = init_contract();
+            let player = get_account(0);
+            let ghost_states = build_initial_ghost_states(300);
 
-                (info, index as u8)
-            } else {
-                (vec![player_info_element], 0)
-            };
+            contract.update_for_finish_mtc_shop(
+                player,
+                2,
+                Default::default(),
+                1,
+                mtc::storage::PlayerMutable {
+                    health: 20,
+                    ghost_states: ghost_states.clone(),
+                    ..Default::default()
+                },
+                None,
+                vec![(None, 300); 3],
+            );
 
-            self.matchmaking_ghosts_info.insert(ep_band, &info);
-            self.matchmaking_ghost_by_index.insert(
-                (ep_band, index),
-                &ghost::build_ghost_from_history(grade_and_board_history),
+            assert_eq!(
+                contract
+                    .player_mtc_mutable
+                    .get(player)
+                    .unwrap()
+                    .grade_and_board_history
+                    .len(),
+                1
             );
+            assert_eq!(contract.player_seed.get(player), Some(1));
+
+            let events = get_recorded_events();
+            assert_eq!(events.len(), 1);
+            assert!(matches!(
+                &events[0],
+                Event::ShopFinished(ShopFinished {
+                    player: p,
+                    turn: 1,
+                    grade: 2,
+                    health: 20,
+                    ghost_states: s,
+                }) if p == &player && s == &ghost_states
+            ));
         }
 
-        fn finish_mtc_turn(
-            &mut self,
-            player: AccountId,
-            mut player_mtc_mutable: mtc::storage::PlayerMutable,
-            new_seed: u64,
-        ) {
-            update_player_mtc_mutable_after_battle(&mut player_mtc_mutable, new_seed);
-            self.player_mtc_mutable.insert(player, &player_mtc_mutable);
-        }
-    }
+        #[ink::test]
+        fn finish_mtc_match_result() {
+            let mut contract = init_contract_with_emo_bases();
+            let player = get_account(0);
+            let board = mtc::Board(vec![Default::default()]);
+            let history = vec![
+                mtc::GradeAndBoard {
+                    grade: 1,
+                    board: Default::default(),
+                },
+                mtc::GradeAndBoard {
+                    grade: 3,
+                    board: board.clone(),
+                },
+            ];
+            // the ghosts are rated by the eps they were registered with
+            let ghosts = vec![
+                (Some(get_account(1)), 400),
+                (None, 300),
+                (Some(get_account(2)), 300),
+            ];
+
+            contract.player_ep.insert(player, &300);
+            contract.player_ep.insert(get_account(1), &500);
+            contract.finish_mtc(
+                player,
+                2,
+                123,
+                ghosts.clone(),
+                &build_initial_ghost_states(300),
+                &history,
+            );
 
-    #[cfg(test)]
-    mod tests {
-        use super::*;
+            let expected = MatchResult {
+                place: 2,
+                old_ep: 300,
+                new_ep: 324,
+                grade: 3,
+                board,
+                turn: 2,
+                ghost_accounts: ghosts.into_iter().map(|(a, _)| a).collect(),
+                seed: 123,
+            };
+            assert_eq!(
+                contract.get_player_match_result(player),
+                Some(expected.clone())
+            );
+            assert_eq!(contract.get_player_rd(get_account(1)), ep::MAX_RD);
+            assert!(contract.get_player_rd(player) < ep::MAX_RD);
+            // the ghost is registered with the new ep, as in the pallet
+            assert_eq!(
+                ghost::MatchmakingStorage::get_ghosts_info(&contract, ep::get_ep_band(324)),
+                Some(vec![(get_current_block_number(), player, 324)])
+            );
 
-        fn set_caller(caller: AccountId) {
-            ink::env::test::set_caller::<Environment>(caller);
+            let events = get_recorded_events();
+            assert_eq!(events.len(), 1);
+            if let Event::MtcFinished(MtcFinished { player: p, result }) = &events[0] {
+                assert_eq!(p, &player);
+                assert_eq!(result, &expected);
+            } else {
+                panic!("unexpected event");
+            }
         }
 
-        fn get_default_accounts() -> ink::env::test::DefaultAccounts<Environment> {
-            ink::env::test::default_accounts::<Environment>()
-        }
+        #[ink::test]
+        fn finish_mtc_challenge() {
+            let mut contract = init_contract_with_emo_bases();
+            let player = get_account(0);
+            let history = vec![mtc::GradeAndBoard {
+                grade: 2,
+                board: mtc::Board(vec![Default::default()]),
+            }];
+
+            contract.player_ep.insert(player, &300);
+            contract
+                .player_mtc_mutable
+                .insert(player, &mtc::storage::PlayerMutable::default());
+            contract.player_mtc_challenge.insert(player, &());
 
-        fn get_account(n: u8) -> AccountId {
-            assert!(n < 255, "not supported");
-            let mut arr = [0xff; 32];
-            arr[0] -= n + 1;
-            AccountId::from(arr)
+            contract.finish_mtc(
+                player,
+                1,
+                0,
+                vec![(None, 300); 3],
+                &build_initial_ghost_states(300),
+                &history,
+            );
+
+            assert_eq!(contract.player_ep.get(player), Some(300));
+            assert_eq!(contract.get_leaderboard(), vec![]);
+            assert_eq!(
+                contract
+                    .matchmaking_ghosts_info_v2
+                    .get(ep::get_ep_band(300)),
+                None
+            );
+            assert!(!contract.player_mtc_mutable.contains(player));
+            assert!(!contract.player_mtc_challenge.contains(player));
+
+            contract
+                .player_mtc_mutable
+                .insert(player, &mtc::storage::PlayerMutable::default());
+
+            contract.finish_mtc(
+                player,
+                1,
+                0,
+                vec![(None, 300); 3],
+                &build_initial_ghost_states(300),
+                &history,
+            );
+
+            // bots don't move the ep
+            assert_eq!(contract.player_ep.get(player), Some(300));
+            assert_eq!(contract.get_leaderboard(), vec![(300, player)]);
+            assert!(contract
+                .matchmaking_ghosts_info_v2
+                .contains(ep::get_ep_band(300)));
         }
 
-        struct AccountIdForDebug(AccountId);
-        impl std::fmt::Debug for AccountIdForDebug {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                let a: &[u8; 32] = self.0.as_ref();
-                write!(f, "a:{}", 254 - a[0])
+        #[ink::test]
+        fn finish_mtc_player_stats() {
+            let mut contract = init_contract_with_emo_bases();
+            let player = get_account(0);
+
+            fn build_history(grade: u8, base_ids: &[u16]) -> Vec<mtc::GradeAndBoard> {
+                vec![mtc::GradeAndBoard {
+                    grade,
+                    board: mtc::Board(
+                        base_ids
+                            .iter()
+                            .map(|&base_id| mtc::BoardEmo {
+                                base_id,
+                                ..Default::default()
+                            })
+                            .collect(),
+                    ),
+                }]
             }
-        }
 
-        fn get_current_block_number() -> BlockNumber {
-            ink::env::block_number::<Environment>()
-        }
+            contract.player_ep.insert(player, &300);
+            assert_eq!(contract.get_player_stats(player), None);
+
+            for (place, grade, base_ids) in [
+                (1, 3, &[1, 2][..]),
+                (1, 2, &[2][..]),
+                (3, 6, &[2, 3][..]),
+                (1, 4, &[1][..]),
+            ] {
+                contract.finish_mtc(
+                    player,
+                    place,
+                    0,
+                    vec![],
+                    &[],
+                    &build_history(grade, base_ids),
+                );
+            }
 
-        fn advance_block() -> BlockNumber {
-            ink::env::test::advance_block::<Environment>();
-            get_current_block_number()
+            // challenges are excluded
+            contract.player_mtc_challenge.insert(player, &());
+            contract.finish_mtc(player, 1, 0, vec![], &[], &build_history(6, &[4]));
+
+            let stats = contract.get_player_stats(player).unwrap();
+            assert_eq!(stats.played_count, 4);
+            assert_eq!(stats.place_counts, [3, 0, 1, 0]);
+            assert_eq!(stats.highest_ep, 300);
+            assert_eq!(stats.emo_counts, vec![(2, 3), (1, 2), (3, 1)]);
+            assert_eq!(stats.best_result.map(|r| (r.place, r.grade)), Some((1, 4)));
+            assert_eq!(stats.win_streak, 1);
+            assert_eq!(stats.max_win_streak, 2);
         }
 
-        fn init_contract() -> Contract {
-            set_caller(get_default_accounts().alice);
-            Contract::new()
+        #[ink::test]
+        fn finish_mtc_achievements() {
+            let mut contract = init_contract_with_emo_bases();
+            let player = get_account(0);
+            let history = vec![mtc::GradeAndBoard {
+                grade: 6,
+                board: Default::default(),
+            }];
+
+            contract.player_ep.insert(player, &300);
+            contract.finish_mtc(player, 2, 0, vec![], &[], &history);
+            assert_eq!(contract.get_player_achievements(player), vec![8]);
+
+            contract.finish_mtc(player, 1, 0, vec![], &[], &history);
+            assert_eq!(contract.get_player_achievements(player), vec![8, 1]);
+
+            let events = get_recorded_events()
+                .into_iter()
+                .filter_map(|e| match e {
+                    Event::AchievementsEarned(AchievementsEarned { player: p, ids }) => {
+                        Some((p, ids))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(events, vec![(player, vec![8]), (player, vec![1])]);
         }
 
         #[ink::test]
-        fn new() {
-            assert_eq!(init_contract().admins, vec![get_default_accounts().alice]);
+        fn get_earnable_achievement_ids() {
+            let contract = init_contract();
+            assert!(!contract.get_earnable_achievement_ids().contains(&9));
         }
 
+        // the bands stored before the registered ep are read at the key of the old layout
         #[ink::test]
-        fn create_or_update_player_ep() {
+        fn get_matchmaking_ghosts_info_legacy() {
             let mut contract = init_contract();
+            let ep_band = ep::get_ep_band(300);
+            let key = ink::primitives::KeyComposer::compute_key(
+                "Contract",
+                "",
+                "matchmaking_ghosts_info",
+            )
+            .unwrap();
+            let legacy: Vec<(BlockNumber, AccountId)> =
+                vec![(1, get_account(0)), (2, get_account(1))];
+            ink::env::set_contract_storage(&(key, ep_band), &legacy);
+            contract.player_ep.insert(get_account(0), &320);
 
-            let account0 = get_account(0);
-            assert_eq!(contract.player_ep.get(account0), None);
-            assert_eq!(contract.create_or_update_player_ep(account0), 300);
-            assert_eq!(contract.player_ep.get(account0), Some(300));
-
-            let account1 = get_account(1);
-            contract.player_ep.insert(account1, &400);
-            assert_eq!(contract.create_or_update_player_ep(account1), 400);
-            assert_eq!(contract.player_ep.get(account1), Some(400));
+            assert_eq!(
+                contract.get_matchmaking_ghosts_info(ep_band),
+                Some(vec![(1, get_account(0), 320), (2, get_account(1), 300)])
+            );
 
-            let account2 = get_account(2);
-            contract.player_ep.insert(account2, &500);
-            let m: mtc::storage::PlayerMutable = Default::default();
-            contract.player_mtc_mutable.insert(account2, &m);
-            assert_eq!(contract.create_or_update_player_ep(account2), 440);
-            assert_eq!(contract.player_ep.get(account2), Some(440));
+            register_ghost(&mut contract, get_account(2), 310, &[Default::default()]);
+            assert_eq!(contract.matchmaking_ghosts_info.get(ep_band), None);
+            assert_eq!(
+                contract.matchmaking_ghosts_info_v2.get(ep_band).unwrap()[..2],
+                [(1, get_account(0), 320), (2, get_account(1), 300)]
+            );
         }
 
         #[ink::test]
@@ -505,7 +2716,7 @@ pub mod contract {
             let ep_band = ep::get_ep_band(ep);
             let mut current_block = get_current_block_number();
 
-            assert_eq!(contract.matchmaking_ghosts_info.get(ep_band), None);
+            assert_eq!(contract.matchmaking_ghosts_info_v2.get(ep_band), None);
             assert_eq!(contract.matchmaking_ghost_by_index.get((ep_band, 0)), None);
 
             fn build_grade_and_board_vec(grade: u8) -> Vec<mtc::GradeAndBoard> {
@@ -524,10 +2735,15 @@ pub mod contract {
                 }
             }
 
-            contract.add_matchmaking_ghost(get_account(0), ep, &build_grade_and_board_vec(0));
+            register_ghost(
+                &mut contract,
+                get_account(0),
+                ep,
+                &build_grade_and_board_vec(0),
+            );
             assert_eq!(
-                contract.matchmaking_ghosts_info.get(ep_band).unwrap(),
-                vec![(current_block, get_account(0))]
+                contract.matchmaking_ghosts_info_v2.get(ep_band).unwrap(),
+                vec![(current_block, get_account(0), ep)]
             );
             assert_eq!(
                 contract
@@ -537,12 +2753,17 @@ pub mod contract {
                 build_ghost(0)
             );
 
-            contract.add_matchmaking_ghost(get_account(1), ep, &build_grade_and_board_vec(1));
+            register_ghost(
+                &mut contract,
+                get_account(1),
+                ep,
+                &build_grade_and_board_vec(1),
+            );
             assert_eq!(
-                contract.matchmaking_ghosts_info.get(ep_band).unwrap(),
+                contract.matchmaking_ghosts_info_v2.get(ep_band).unwrap(),
                 vec![
-                    (current_block, get_account(0)),
-                    (current_block, get_account(1))
+                    (current_block, get_account(0), ep),
+                    (current_block, get_account(1), ep)
                 ]
             );
             assert_eq!(
@@ -562,12 +2783,17 @@ pub mod contract {
 
             current_block = advance_block();
 
-            contract.add_matchmaking_ghost(get_account(0), ep, &build_grade_and_board_vec(2));
+            register_ghost(
+                &mut contract,
+                get_account(0),
+                ep,
+                &build_grade_and_board_vec(2),
+            );
             assert_eq!(
-                contract.matchmaking_ghosts_info.get(ep_band).unwrap(),
+                contract.matchmaking_ghosts_info_v2.get(ep_band).unwrap(),
                 vec![
-                    (current_block, get_account(0)),
-                    (current_block - 1, get_account(1))
+                    (current_block, get_account(0), ep),
+                    (current_block - 1, get_account(1), ep)
                 ]
             );
             assert_eq!(
@@ -581,19 +2807,36 @@ pub mod contract {
             advance_block();
 
             for n in 2u8..19 {
-                contract.add_matchmaking_ghost(get_account(n), ep, &build_grade_and_board_vec(3));
+                register_ghost(
+                    &mut contract,
+                    get_account(n),
+                    ep,
+                    &build_grade_and_board_vec(3),
+                );
             }
             assert_eq!(
-                contract.matchmaking_ghosts_info.get(ep_band).unwrap().len(),
+                contract
+                    .matchmaking_ghosts_info_v2
+                    .get(ep_band)
+                    .unwrap()
+                    .len(),
                 19
             );
 
             current_block = advance_block();
 
-            contract.add_matchmaking_ghost(get_account(19), ep, &build_grade_and_board_vec(4));
-            let result = contract.matchmaking_ghosts_info.get(ep_band).unwrap();
+            register_ghost(
+                &mut contract,
+                get_account(19),
+                ep,
+                &build_grade_and_board_vec(4),
+            );
+            let result = contract.matchmaking_ghosts_info_v2.get(ep_band).unwrap();
             assert_eq!(result.len(), 20);
-            assert_eq!(result.last().unwrap(), &(current_block, get_account(19)));
+            assert_eq!(
+                result.last().unwrap(),
+                &(current_block, get_account(19), ep)
+            );
             assert_eq!(
                 contract
                     .matchmaking_ghost_by_index
@@ -602,8 +2845,8 @@ pub mod contract {
                 build_ghost(4)
             );
 
-            let mut before = contract.matchmaking_ghosts_info.get(ep_band).unwrap();
-            // println!("{:?}", before.clone().into_iter().map(|(b, a)| (b, AccountIdForDebug(a))).collect::<Vec<_>>());
+            let mut before = contract.matchmaking_ghosts_info_v2.get(ep_band).unwrap();
+            // println!("{:?}", before.clone().into_iter().map(|(b, a, e)| (b, AccountIdForDebug(a), e)).collect::<Vec<_>>());
             assert_eq!(
                 contract
                     .matchmaking_ghost_by_index
@@ -611,11 +2854,16 @@ pub mod contract {
                     .unwrap(),
                 build_ghost(1)
             );
-            contract.add_matchmaking_ghost(get_account(20), ep, &build_grade_and_board_vec(5));
-            let after = contract.matchmaking_ghosts_info.get(ep_band).unwrap();
-            // println!("{:?}", after.clone().into_iter().map(|(b, a)| (b, AccountIdForDebug(a))).collect::<Vec<_>>());
+            register_ghost(
+                &mut contract,
+                get_account(20),
+                ep,
+                &build_grade_and_board_vec(5),
+            );
+            let after = contract.matchmaking_ghosts_info_v2.get(ep_band).unwrap();
+            // println!("{:?}", after.clone().into_iter().map(|(b, a, e)| (b, AccountIdForDebug(a), e)).collect::<Vec<_>>());
             assert_eq!(after.len(), 20);
-            before[1] = (current_block, get_account(20));
+            before[1] = (current_block, get_account(20), ep);
             assert_eq!(after, before);
             assert_eq!(
                 contract
@@ -628,8 +2876,8 @@ pub mod contract {
             current_block = advance_block();
 
             assert_eq!(
-                contract.matchmaking_ghosts_info.get(ep_band).unwrap()[10],
-                (2, get_account(10))
+                contract.matchmaking_ghosts_info_v2.get(ep_band).unwrap()[10],
+                (2, get_account(10), ep)
             );
             assert_eq!(
                 contract
@@ -642,10 +2890,15 @@ pub mod contract {
                     .grade,
                 3
             );
-            contract.add_matchmaking_ghost(get_account(10), ep, &build_grade_and_board_vec(6));
+            register_ghost(
+                &mut contract,
+                get_account(10),
+                ep,
+                &build_grade_and_board_vec(6),
+            );
             assert_eq!(
-                contract.matchmaking_ghosts_info.get(ep_band).unwrap()[10],
-                (current_block, get_account(10))
+                contract.matchmaking_ghosts_info_v2.get(ep_band).unwrap()[10],
+                (current_block, get_account(10), ep)
             );
             assert_eq!(
                 contract
@@ -659,5 +2912,245 @@ pub mod contract {
                 6
             );
         }
+
+        // the storage values of finish_mtc_shop at the max turn with the worst case boards and ghosts,
+        // a value over the static buffer of ink! can't be read or written on chain,
+        // so the shop traps before the history reaches the max turn and the player has to forfeit
+        #[ink::test]
+        fn finish_mtc_shop_worst_case_storage_sizes() {
+            use common::mtc::worst_case;
+
+            const STATIC_BUFFER_SIZE: usize = 1 << 14;
+
+            let emo_bases = worst_case::build_emo_bases();
+            let (built, fixed) = worst_case::build_deck_base_ids();
+            let ghost = worst_case::build_ghost(&emo_bases);
+            let player_immutable: PlayerImmutable = (
+                setup::build_pool(&built, &emo_bases, &fixed, &built).unwrap(),
+                (1..=3)
+                    .map(|i| (Some(get_account(i)), ep::INITIAL_EP, ghost.clone()))
+                    .collect(),
+            );
+            let player_mutable = mtc::storage::PlayerMutable {
+                health: setup::PLAYER_INITIAL_HEALTH,
+                grade_and_board_history: worst_case::build_grade_and_board_history(&emo_bases),
+                upgrade_coin: None,
+                ghost_states: setup::build_initial_ghost_states(),
+                battle_ghost_index: 0,
+            };
+
+            let sizes = [
+                (
+                    "player_operations",
+                    worst_case::build_player_operations().encoded_size(),
+                ),
+                ("emo_bases", emo_bases.encoded_size()),
+                ("player_mtc_immutable", player_immutable.encoded_size()),
+                ("player_mtc_mutable", player_mutable.encoded_size()),
+            ];
+            assert_eq!(
+                sizes,
+                [
+                    ("player_operations", 1793),
+                    ("emo_bases", 2977),
+                    ("player_mtc_immutable", 76992),
+                    ("player_mtc_mutable", 26111),
+                ]
+            );
+            assert_eq!(
+                sizes
+                    .iter()
+                    .filter(|(_, size)| *size > STATIC_BUFFER_SIZE)
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>(),
+                vec!["player_mtc_immutable", "player_mtc_mutable"]
+            );
+        }
+
+        // runs finish_mtc_shop with all the worst case operations, boards and ghost boards,
+        // with the longest histories the off-chain env can read (9600 bytes per value)
+        #[ink::test]
+        fn finish_mtc_shop_worst_case() {
+            use common::mtc::worst_case;
+
+            const OFF_CHAIN_READ_BUFFER_SIZE: usize = 9600;
+
+            let mut contract = init_contract();
+            let player = get_account(0);
+            let seed_secret = [7u8; 32];
+
+            let emo_bases = worst_case::build_emo_bases();
+            let (built, fixed) = worst_case::build_deck_base_ids();
+            let pool = setup::build_pool(&built, &emo_bases, &fixed, &built).unwrap();
+            let mut ghost = worst_case::build_ghost(&emo_bases);
+            let mut history = worst_case::build_grade_and_board_history(&emo_bases);
+
+            let build_player_immutable = |ghost: &mtc::Ghost| -> PlayerImmutable {
+                (
+                    pool.clone(),
+                    (1..=3)
+                        .map(|i| (Some(get_account(i)), ep::INITIAL_EP, ghost.clone()))
+                        .collect(),
+                )
+            };
+            while build_player_immutable(&ghost).encoded_size() > OFF_CHAIN_READ_BUFFER_SIZE {
+                ghost.history.pop();
+            }
+            // the history gets one more entry, which is read again after the shop
+            while (&history, history.last()).encoded_size() > OFF_CHAIN_READ_BUFFER_SIZE {
+                history.pop();
+            }
+            assert_eq!((history.len(), ghost.history.len()), (10, 3));
+
+            let player_mutable = mtc::storage::PlayerMutable {
+                health: setup::PLAYER_INITIAL_HEALTH,
+                grade_and_board_history: history,
+                upgrade_coin: None,
+                ghost_states: setup::build_initial_ghost_states(),
+                battle_ghost_index: 0,
+            };
+
+            set_caller(player);
+            contract.commit_seed(build_seed_commitment(&seed_secret));
+            advance_to_seed_reveal();
+            contract.emo_bases = Some(emo_bases);
+            contract.player_ep.insert(player, &ep::INITIAL_EP);
+            contract.player_seed.insert(player, &1);
+            contract
+                .player_mtc_immutable_v2
+                .insert(player, &build_player_immutable(&ghost));
+            contract.player_mtc_mutable.insert(player, &player_mutable);
+
+            let callee = ink::env::account_id::<Environment>();
+            let (reads, writes) = ink::env::test::get_contract_storage_rw::<Environment>(&callee);
+            contract.finish_mtc_shop(
+                worst_case::build_player_operations(),
+                seed_secret,
+                [0u8; 32],
+            );
+            let (new_reads, new_writes) =
+                ink::env::test::get_contract_storage_rw::<Environment>(&callee);
+
+            assert_eq!(
+                contract
+                    .get_player_mtc_mutable(player)
+                    .map(|m| m.grade_and_board_history.len()),
+                Some(player_mutable.grade_and_board_history.len() + 1)
+            );
+            // each storage access is charged on chain
+            assert_eq!((new_reads - reads, new_writes - writes), (8, 6));
+        }
+
+        fn init_contract_with_skins() -> Contract {
+            let mut contract = init_contract();
+            let mut emo_bases = emo::Bases::new();
+            for id in 1..=3 {
+                emo_bases.add(emo::Base {
+                    id,
+                    ..Default::default()
+                });
+            }
+            contract.emo_bases = Some(emo_bases);
+            contract.deck_fixed_emo_base_ids = Some(vec![3]);
+
+            for (skin_id, base_id) in [(10, 1), (11, 1), (20, 2), (30, 3)] {
+                contract.set_skin(
+                    skin_id,
+                    Some(Skin {
+                        base_id,
+                        codepoint: Some(0x1f600),
+                        frame: 1,
+                    }),
+                );
+            }
+            contract
+        }
+
+        #[ink::test]
+        fn grant_and_revoke_skin() {
+            let mut contract = init_contract_with_skins();
+            let player = get_account(0);
+
+            assert_eq!(contract.get_skin(10).map(|s| s.base_id), Some(1));
+            assert!(!contract.get_player_skin_owned(player, 10));
+            contract.grant_skin(player, 10);
+            assert!(contract.get_player_skin_owned(player, 10));
+            contract.revoke_skin(player, 10);
+            assert!(!contract.get_player_skin_owned(player, 10));
+
+            contract.grant_skin(player, 10);
+            contract.grant_skin(get_account(1), 10);
+            contract.grant_skin(get_account(1), 10);
+            contract.set_skin(10, None);
+            assert_eq!(contract.get_skin(10), None);
+            assert!(!contract.get_player_skin_owned(player, 10));
+            assert!(!contract.get_player_skin_owned(get_account(1), 10));
+            assert_eq!(contract.skin_owners.get(10), None);
+
+            // a new skin with the same id isn't owned by the former owners
+            contract.set_skin(
+                10,
+                Some(Skin {
+                    base_id: 1,
+                    codepoint: None,
+                    frame: 0,
+                }),
+            );
+            assert!(!contract.get_player_skin_owned(player, 10));
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "grant_skin: skin not found")]
+        fn grant_skin_not_found() {
+            let mut contract = init_contract_with_skins();
+            contract.grant_skin(get_account(0), 40);
+        }
+
+        #[ink::test]
+        fn assert_skin_selection() {
+            let mut contract = init_contract_with_skins();
+            let player = get_account(0);
+            for skin_id in [10, 11, 20, 30] {
+                contract.grant_skin(player, skin_id);
+            }
+
+            contract.assert_skin_selection(player, &[1, 2], &[]);
+            contract.assert_skin_selection(player, &[1, 2], &[11, 20, 30]);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "start_mtc: skin not owned")]
+        fn assert_skin_selection_not_owned() {
+            let mut contract = init_contract_with_skins();
+            let player = get_account(0);
+            contract.grant_skin(get_account(1), 10);
+            contract.assert_skin_selection(player, &[1, 2], &[10]);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "start_mtc: duplicate skin base")]
+        fn assert_skin_selection_duplicate_base() {
+            let mut contract = init_contract_with_skins();
+            let player = get_account(0);
+            contract.grant_skin(player, 10);
+            contract.grant_skin(player, 11);
+            contract.assert_skin_selection(player, &[1, 2], &[10, 11]);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "start_mtc: too many skins")]
+        fn assert_skin_selection_too_many() {
+            let contract = init_contract_with_skins();
+            contract.assert_skin_selection(get_account(0), &[1, 2], &[10, 10, 10, 10]);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "start_mtc: skin base not in the pool")]
+        fn assert_skin_selection_not_in_pool() {
+            let mut contract = init_contract_with_skins();
+            let player = get_account(0);
+            contract.grant_skin(player, 20);
+            contract.assert_skin_selection(player, &[1], &[20]);
+        }
     }
 }
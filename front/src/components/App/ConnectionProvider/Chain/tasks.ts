@@ -1,11 +1,36 @@
 import type { ApiPromise } from "@polkadot/api"
 import { encodeAddress } from "@polkadot/util-crypto"
 
-import { tx, createType, buildKeyringPair, type emo_Bases } from "common"
+import {
+  tx,
+  createType,
+  buildKeyringPair,
+  type emo_Bases,
+  type mtc_Emo,
+  type mtc_Ghost,
+  type mtc_GhostState,
+  type mtc_GradeAndBoard,
+} from "common"
 import type { Connection } from "../tasks"
 import { buildEmoBases } from "~/misc/mtcUtils"
 import { getOebEnv } from "~/misc/env"
-import type { Option, Vec, u16, u64 } from "@polkadot/types-codec"
+import type { Option, Struct, Vec, u8, u16, u64 } from "@polkadot/types-codec"
+import type { ITuple } from "@polkadot/types-codec/types"
+import type { AccountId, BlockNumber } from "@polkadot/types/interfaces/runtime"
+
+// game_PlayerView, returned by the game_playerView rpc
+interface PlayerView extends Struct {
+  readonly health: u8
+  readonly grade_and_board_history: Vec<mtc_GradeAndBoard>
+  readonly upgrade_coin: Option<u8>
+  readonly ghosts: Vec<ITuple<[Option<AccountId>, u16, mtc_Ghost]>>
+  readonly ghost_states: Vec<mtc_GhostState>
+  readonly battle_ghost_index: u8
+  readonly pool: Vec<mtc_Emo>
+}
+
+const getPlayerView = (api: ApiPromise, address: string) =>
+  (api.rpc as any).game.playerView(address) as Promise<Option<PlayerView>>
 
 const endpointStorageKey = "endpointV5"
 
@@ -40,22 +65,41 @@ const buildConnectionQuery = (api: ApiPromise): Connection["query"] => ({
     ((await api.query.game.deckFixedEmoBaseIds()) as Option<Vec<u16>>).unwrap(),
   deckBuiltEmoBaseIds: async () =>
     ((await api.query.game.deckBuiltEmoBaseIds()) as Option<Vec<u16>>).unwrap(),
-  matchmakingGhostsInfo: () => {
-    throw new Error("unimplemented")
-  },
-  matchmakingGhostByIndex: () => {
-    throw new Error("unimplemented")
-  },
+  matchmakingGhostsInfo: (band) =>
+    api.query.game.matchmakingGhostsInfo(band) as Promise<
+      Option<Vec<ITuple<[BlockNumber, AccountId, u16]>>>
+    >,
+  matchmakingGhostByIndex: (band, index) =>
+    api.query.game.matchmakingGhostByIndex([band, index]) as Promise<Option<mtc_Ghost>>,
   leaderboard: () => {
     throw new Error("unimplemented")
   },
   playerEp: (address) => api.query.game.playerEp(address) as Promise<Option<u16>>,
   playerSeed: (address) => api.query.game.playerSeed(address) as Promise<Option<u64>>,
-  playerMtcImmutable: (_address) => {
-    throw new Error("unimplemented")
+  playerMtcImmutable: async (address) => {
+    const view = (await getPlayerView(api, address)).unwrap()
+
+    return [
+      view.pool,
+      view.ghosts.map(
+        ([accountId, , ghost]) => [accountId.unwrapOrDefault(), ghost] as [AccountId, mtc_Ghost]
+      ),
+    ]
   },
-  playerMtcMutable: (_address) => {
-    throw new Error("unimplemented")
+  playerMtcMutable: async (address) => {
+    const view = await getPlayerView(api, address)
+    if (view.isNone) {
+      return createType("Option<mtc_storage_PlayerMutable>", null)
+    }
+
+    const v = view.unwrap()
+    return createType("Option<mtc_storage_PlayerMutable>", {
+      health: v.health,
+      grade_and_board_history: v.grade_and_board_history,
+      upgrade_coin: v.upgrade_coin,
+      ghost_states: v.ghost_states,
+      battle_ghost_index: v.battle_ghost_index,
+    })
   },
 })
 
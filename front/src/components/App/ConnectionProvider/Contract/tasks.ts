@@ -1,10 +1,12 @@
 import type { ApiPromise } from "@polkadot/api"
 import type { ContractPromise } from "@polkadot/api-contract"
-import { encodeAddress } from "@polkadot/util-crypto"
+import { u8aToHex, hexToU8a } from "@polkadot/util"
+import { encodeAddress, blake2AsU8a, randomAsU8a } from "@polkadot/util-crypto"
 
 import { queryContract, txContract, createType, EnvContract, getGameContract } from "common"
 import { buildEmoBases } from "~/misc/mtcUtils"
 import type { Connection } from "../tasks"
+import type { u32 } from "@polkadot/types-codec"
 
 export const buildConnection = async (api: ApiPromise, env: EnvContract): Promise<Connection> => {
   const gameContract = getGameContract(api, env.gameAddress, env.ink)
@@ -16,7 +18,7 @@ export const buildConnection = async (api: ApiPromise, env: EnvContract): Promis
   return {
     kind: "contract",
     query: buildConnectionQuery(gameContract, env.ink),
-    tx: buildConnectionTx(gameContract),
+    tx: buildConnectionTx(gameContract, env.ink),
     emoBases,
     api: () => api,
     transformAddress: (a) => encodeAddress(a, api.registry.chainSS58),
@@ -54,7 +56,7 @@ const buildConnectionQuery = (
     query(
       gameContract,
       inkVersion,
-      "Option<Vec<(BlockNumber, AccountId)>>",
+      "Option<Vec<(BlockNumber, AccountId, u16)>>",
       "getMatchmakingGhostsInfo",
       [band]
     ),
@@ -72,9 +74,9 @@ const buildConnectionQuery = (
       await query(
         gameContract,
         inkVersion,
-        `Option<(Vec<mtc_Emo>, Vec<${inkVersion === 4 ? "Option<" : ""}(AccountId, mtc_Ghost)${
-          inkVersion === 4 ? ">" : ""
-        }>)>`,
+        `Option<(Vec<mtc_Emo>, Vec<(${
+          inkVersion === 4 ? "Option<AccountId>, u16" : "AccountId"
+        }, mtc_Ghost)>)>`,
         "getPlayerMtcImmutable",
         [address]
       )
@@ -82,10 +84,10 @@ const buildConnectionQuery = (
 
     return [
       codec[0],
-      codec[1].toArray().map((x: any) => {
-        const t = inkVersion === 4 ? x.unwrapOrDefault() : x
-        return [t[0], t[1]]
-      }),
+      // bot ghosts have no account, and the ep a ghost is rated by isn't shown
+      codec[1]
+        .toArray()
+        .map((x: any) => (inkVersion === 4 ? [x[0].unwrapOrDefault(), x[2]] : [x[0], x[1]])),
     ]
   },
   playerMtcMutable: (address) =>
@@ -94,30 +96,118 @@ const buildConnectionQuery = (
     ]),
 })
 
-const buildConnectionTx = (gameContract: ContractPromise): Connection["tx"] => ({
+const seedSecretStoragePrefix = "mtcSeedSecret:"
+
+// keyed by the commitment, so a secret stored before its tx lands survives reloads
+const getSeedSecretStorageKey = (address: string, commitment: string) =>
+  `${seedSecretStoragePrefix}${address}:${commitment}`
+
+// SEED_ENTROPY_DELAY and SEED_REVEAL_PERIOD of the contract
+const seedEntropyDelay = 1
+const seedRevealPeriod = 300
+
+const buildSeedCommitment = (secret: Uint8Array) => blake2AsU8a(secret, 256)
+
+const getSeedSecret = async (
+  gameContract: ContractPromise,
+  inkVersion: number,
+  address: string
+) => {
+  const commitment = await query(
+    gameContract,
+    inkVersion,
+    "Option<([u8; 32], u32)>",
+    "getPlayerSeedCommitment",
+    [address]
+  )
+  if (commitment.isNone) {
+    return undefined
+  }
+  const [hash, blockNumber] = commitment.unwrap()
+  const getBlockNumber = async () =>
+    ((await gameContract.api.query.system.number()) as u32).toNumber()
+  if ((await getBlockNumber()) > blockNumber.toNumber() + seedRevealPeriod) {
+    return undefined
+  }
+  const secret = localStorage.getItem(getSeedSecretStorageKey(address, hash.toHex()))
+  if (!secret) {
+    return undefined
+  }
+  // the next tx is in a block after the entropy block
+  while ((await getBlockNumber()) < blockNumber.toNumber() + seedEntropyDelay) {
+    await new Promise((resolve) => setTimeout(resolve, 1_000))
+  }
+  return hexToU8a(secret)
+}
+
+const setSeedSecret = (address: string, secret: Uint8Array) =>
+  localStorage.setItem(
+    getSeedSecretStorageKey(address, u8aToHex(buildSeedCommitment(secret))),
+    u8aToHex(secret)
+  )
+
+const removeSeedSecret = (address: string, secret: Uint8Array) =>
+  localStorage.removeItem(getSeedSecretStorageKey(address, u8aToHex(buildSeedCommitment(secret))))
+
+const buildConnectionTx = (
+  gameContract: ContractPromise,
+  inkVersion: number
+): Connection["tx"] => ({
   startMtc: async (deckEmoBaseIds, account) => {
     if (account.kind !== "contract") {
       throw new Error("invalid connection kind")
     }
 
-    await txContract(gameContract, "startMtc", [deckEmoBaseIds], {
-      address: account.address,
-      signer: account.signer,
-    })
+    const options = { address: account.address, signer: account.signer }
+
+    let seedSecret = await getSeedSecret(gameContract, inkVersion, account.address)
+    if (!seedSecret) {
+      // a lost secret is replaced by forfeiting the current mtc
+      seedSecret = randomAsU8a(32)
+      setSeedSecret(account.address, seedSecret)
+      await txContract(gameContract, "commitSeed", [buildSeedCommitment(seedSecret)], options)
+      seedSecret = await getSeedSecret(gameContract, inkVersion, account.address)
+      if (!seedSecret) {
+        throw new Error("seed secret not found")
+      }
+    }
+
+    const nextSeedSecret = randomAsU8a(32)
+    setSeedSecret(account.address, nextSeedSecret)
+
+    await txContract(
+      gameContract,
+      "startMtc",
+      [deckEmoBaseIds, [], seedSecret, buildSeedCommitment(nextSeedSecret)],
+      options
+    )
+    removeSeedSecret(account.address, seedSecret)
   },
   finishMtcShop: async (ops, account) => {
     if (account.kind !== "contract") {
       throw new Error("invalid connection kind")
     }
 
+    const seedSecret = await getSeedSecret(gameContract, inkVersion, account.address)
+    if (!seedSecret) {
+      throw new Error("seed secret not found")
+    }
+    const nextSeedSecret = randomAsU8a(32)
+    setSeedSecret(account.address, nextSeedSecret)
+
     await txContract(
       gameContract,
       "finishMtcShop",
-      [createType("Vec<mtc_shop_PlayerOperation>", ops).toU8a()],
+      [
+        createType("Vec<mtc_shop_PlayerOperation>", ops).toU8a(),
+        seedSecret,
+        buildSeedCommitment(nextSeedSecret),
+      ],
       {
         address: account.address,
         signer: account.signer,
       }
     )
+    removeSeedSecret(account.address, seedSecret)
   },
 })
@@ -21,7 +21,9 @@ export interface Connection {
   query: {
     deckFixedEmoBaseIds: () => Promise<Vec<u16>>
     deckBuiltEmoBaseIds: () => Promise<Vec<u16>>
-    matchmakingGhostsInfo: (band: number) => Promise<Option<Vec<ITuple<[BlockNumber, AccountId]>>>>
+    matchmakingGhostsInfo: (
+      band: number
+    ) => Promise<Option<Vec<ITuple<[BlockNumber, AccountId, u16]>>>>
     matchmakingGhostByIndex: (band: number, index: number) => Promise<Option<mtc_Ghost>>
     leaderboard: () => Promise<Vec<ITuple<[u16, AccountId]>>>
     playerEp: (address: string) => Promise<Option<u16>>
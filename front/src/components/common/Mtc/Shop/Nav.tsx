@@ -1,5 +1,7 @@
 import * as React from "react"
 import BN from "bn.js"
+import type { ApiPromise } from "@polkadot/api"
+import type { SubmittableExtrinsic } from "@polkadot/api/types"
 
 import { getGradeText, getCoinText } from "~/misc/mtcUtils"
 import { isMulliganAvailable } from "./tasks"
@@ -9,7 +11,13 @@ import { PowButton } from "~/components/common/PowButton"
 import { Identicon } from "~/components/common/Identicon"
 import { AccountContext, useAccount } from "~/components/App/ConnectionProvider/tasks"
 
-export type FinishFn = { kind: "pow"; fn: (s: BN) => void } | { kind: "no-pow"; fn: () => void }
+export type FinishFn =
+  | {
+      kind: "pow"
+      fn: (s: BN) => void
+      call: (t: ApiPromise["tx"]) => SubmittableExtrinsic<"promise">
+    }
+  | { kind: "no-pow"; fn: () => void }
 
 export function Nav(props: {
   health: number
@@ -78,7 +86,11 @@ export function Nav(props: {
             Upgrade {props.upgradeCoin === null ? "" : getCoinText(props.upgradeCoin)}
           </button>
           {props.finishFn.kind === "pow" ? (
-            <PowButtonWrapper onClick={props.finishFn.fn} disabled={props.disabled} />
+            <PowButtonWrapper
+              onClick={props.finishFn.fn}
+              call={props.finishFn.call}
+              disabled={props.disabled}
+            />
           ) : (
             <button
               className={"button is-strong"}
@@ -94,14 +106,23 @@ export function Nav(props: {
   )
 }
 
-function PowButtonWrapper(props: { onClick: (solution: BN) => void; disabled: boolean }) {
+function PowButtonWrapper(props: {
+  onClick: (solution: BN) => void
+  call: (t: ApiPromise["tx"]) => SubmittableExtrinsic<"promise">
+  disabled: boolean
+}) {
   const account = useAccount()
   if (account.kind !== "chain") {
     throw new Error("not chain")
   }
   const sessionAccount = account.session
   return (
-    <PowButton account={sessionAccount} onClick={props.onClick} disabled={props.disabled}>
+    <PowButton
+      account={sessionAccount}
+      call={props.call}
+      onClick={props.onClick}
+      disabled={props.disabled}
+    >
       Battle!
     </PowButton>
   )
@@ -94,6 +94,10 @@ export function Shop(props: {
       ? {
           kind: "pow",
           fn: (solution: BN) => props.startBattle.fn(shopState.playerOperations, solution),
+          call: (t) =>
+            t.game.finishMtcShop(
+              createType("Vec<mtc_shop_PlayerOperation>", shopState.playerOperations)
+            ),
         }
       : { kind: "no-pow", fn: () => props.startBattle.fn(shopState.playerOperations) }
 
@@ -1,41 +1,61 @@
 import * as React from "react"
 import BN from "bn.js"
+import type { ApiPromise } from "@polkadot/api"
+import type { SubmittableExtrinsic } from "@polkadot/api/types"
 
 import {
+  useConnection,
   useAccountUpdater,
   AccountChainPlayer,
   AccountChainSession,
 } from "~/components/App/ConnectionProvider/tasks"
-import { solvePow } from "~/misc/pow"
+import { solvePow, getPowThreshold } from "~/misc/pow"
 
 export function PowButton(props: {
   account: AccountChainPlayer | AccountChainSession
+  // the solution is only valid for this call with these args, so it's solved on click
+  call: (t: ApiPromise["tx"]) => SubmittableExtrinsic<"promise">
   onClick: (solution: BN) => void
   disabled?: boolean
   children: React.ReactNode
 }) {
+  const connection = useConnection()
   const updateAccount = useAccountUpdater()
-  const [solution, setSolution] = React.useState<BN | null>(null)
+  const [isSolving, setIsSolving] = React.useState(false)
+  const isSubscribed = React.useRef(true)
 
   const disabled = props.disabled === undefined ? false : props.disabled
 
   React.useEffect(() => {
-    setSolution(null)
-    let isSubscribed = true
-
-    solvePow(props.account.address, props.account.powCount).then((s) => {
-      if (isSubscribed) {
-        setSolution(s)
-      }
-    })
-
+    isSubscribed.current = true
     return () => {
-      isSubscribed = false
+      isSubscribed.current = false
     }
-  }, [props.account.address])
+  }, [])
 
-  const onClick = (s: BN) => {
+  const onClick = async () => {
     const account = props.account
+    const api = connection.api()
+    setIsSolving(true)
+
+    let solution: BN
+    try {
+      const threshold = await getPowThreshold(api, account.address)
+      solution = await solvePow(
+        account.address,
+        account.powCount,
+        props.call(api.tx).method.toU8a(),
+        threshold
+      )
+    } finally {
+      if (isSubscribed.current) {
+        setIsSolving(false)
+      }
+    }
+    if (!isSubscribed.current) {
+      return
+    }
+
     if ("mnemonic" in account) {
       updateAccount((a) => ({
         ...a,
@@ -47,19 +67,15 @@ export function PowButton(props: {
         player: { ...account, powCount: account.powCount + 1 },
       }))
     }
-    props.onClick(s)
+    props.onClick(solution)
   }
 
-  return solution ? (
-    <button
-      className={"button is-strong has-check"}
-      onClick={() => onClick(solution)}
-      disabled={disabled}
-    >
+  return isSolving ? (
+    <button className={"button is-strong has-clock"} disabled={true}>
       {props.children}
     </button>
   ) : (
-    <button className={"button is-strong has-clock"} disabled={true}>
+    <button className={"button is-strong"} onClick={onClick} disabled={disabled}>
       {props.children}
     </button>
   )
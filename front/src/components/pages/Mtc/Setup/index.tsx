@@ -1,6 +1,8 @@
 import * as React from "react"
 import BN from "bn.js"
 
+import { createType } from "common"
+
 import { Decks } from "./Decks"
 import { PowButton } from "~/components/common/PowButton"
 import { InternalLink } from "~/components/common/InternalLink"
@@ -49,6 +51,11 @@ export function Setup(props: {
                   account.kind === "chain" ? (
                     <PowButton
                       account={account.session.isActive ? account.session : account.player}
+                      call={(t) =>
+                        account.session.isActive
+                          ? t.game.startMtcBySession(createType("Vec<u16>", deck))
+                          : t.game.startMtc(account.session.address, createType("Vec<u16>", deck))
+                      }
                       onClick={startMtc}
                     >
                       Start
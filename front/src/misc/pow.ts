@@ -1,13 +1,34 @@
 import BN from "bn.js"
+import type { ApiPromise } from "@polkadot/api"
+import type { Option, Struct, u32 } from "@polkadot/types-codec"
 import { decodeAddress } from "@polkadot/util-crypto"
 
-import { getPowWasmBytes, getPowSolutionBNFromBytes } from "common"
+import { getPowWasmBytes, getPowSolutionBNFromBytes, getPowCallHash } from "common"
 
 import PowWorker from "./pow.worker"
 
 const wasmBytes = getPowWasmBytes()
 
-export const solvePow = (address: string, powCount: number) =>
+// transactionPaymentPow_QuotaInfo, returned by the transactionPaymentPow_quotaInfo rpc
+interface QuotaInfo extends Struct {
+  readonly used: u32
+  readonly max_count: Option<u32>
+  readonly threshold: u32
+}
+
+const getQuotaInfo = (api: ApiPromise, address: string) =>
+  (api.rpc as any).transactionPaymentPow.quotaInfo(address) as Promise<QuotaInfo>
+
+export const getPowThreshold = async (api: ApiPromise, address: string) =>
+  (await getQuotaInfo(api, address)).threshold.toNumber()
+
+export const solvePow = (
+  address: string,
+  powCount: number,
+  // the scale encoded call
+  call: Uint8Array,
+  threshold: number
+) =>
   new Promise((resolve: (s: BN) => void, reject) => {
     const worker = new PowWorker()
 
@@ -29,5 +50,7 @@ export const solvePow = (address: string, powCount: number) =>
       wasmBytes,
       publicKey: decodeAddress(address),
       count: powCount,
+      callHash: getPowCallHash(call),
+      threshold,
     })
   })
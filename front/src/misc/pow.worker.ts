@@ -12,7 +12,13 @@ self.onmessage = async (evt) => {
 
   try {
     if (data.type === "start") {
-      const solution = await solvePowByBytes(data.wasmBytes, data.publicKey, data.count)
+      const solution = await solvePowByBytes(
+        data.wasmBytes,
+        data.publicKey,
+        data.count,
+        data.callHash,
+        data.threshold
+      )
 
       self.postMessage({
         type: "done",